@@ -0,0 +1,212 @@
+//! Planimetric and surface area reporting.
+use super::*;
+use poly::Boundary;
+
+/// The planimetric (2D) and true surface (3D) area of a surface, in world units (square
+/// metres).
+///
+/// Areas can be limited to within a boundary polygon and/or an elevation band.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SurfaceArea {
+    planimetric: f64,
+    surface: f64,
+}
+
+#[wasm_bindgen]
+impl TriangleMeshSurface {
+    /// The area of the mesh, computed exactly from its triangles.
+    ///
+    /// `boundary` is an optional clipping polygon as flat world XY coordinates (pass an empty
+    /// array for none). `min_z` and `max_z` optionally limit the area to an elevation band.
+    /// Vertical faces have no planimetric area, and are excluded when clipping to a boundary.
+    pub fn area(
+        &self,
+        boundary: &[f64],
+        min_z: Option<f64>,
+        max_z: Option<f64>,
+    ) -> Result<SurfaceArea, String> {
+        init_panic_hook();
+
+        let mut a = SurfaceArea::default();
+        a.add_tris(self.tris(), boundary, min_z, max_z)?;
+        Ok(a)
+    }
+}
+
+#[wasm_bindgen]
+impl SurfaceArea {
+    /// The area estimated from a tile's grid (stored, render space `zs`).
+    ///
+    /// Sum the tiles of an object at a chosen LOD to estimate the area of large meshes
+    /// cheaply, the coarser the LOD the more the surface area is underestimated.
+    /// See [`TriangleMeshSurface::area`] for the other arguments.
+    pub fn from_tile_zs(
+        extents: &Extents3,
+        tile_idx: u32,
+        zs: Vec<f32>,
+        boundary: &[f64],
+        min_z: Option<f64>,
+        max_z: Option<f64>,
+    ) -> Result<SurfaceArea, String> {
+        init_panic_hook();
+
+        if zs.len() != COUNT.pow(2) {
+            return Err(format!("expecting {} tile zs", COUNT.pow(2)));
+        }
+
+        let tris = tile_tris(extents, TileId::from_num(tile_idx), zs);
+        let mut a = SurfaceArea::default();
+        a.add_tris(tris.into_iter(), boundary, min_z, max_z)?;
+        Ok(a)
+    }
+
+    /// Adds another area into this one (eg accumulating tiles).
+    pub fn merge(&mut self, other: &SurfaceArea) {
+        self.planimetric += other.planimetric;
+        self.surface += other.surface;
+    }
+
+    /// The area projected onto the XY plane.
+    pub fn planimetric(&self) -> f64 {
+        self.planimetric
+    }
+
+    /// The true (sloped) area of the surface.
+    pub fn surface(&self) -> f64 {
+        self.surface
+    }
+}
+
+impl SurfaceArea {
+    fn add_tris<I>(
+        &mut self,
+        tris: I,
+        boundary: &[f64],
+        min_z: Option<f64>,
+        max_z: Option<f64>,
+    ) -> Result<(), String>
+    where
+        I: Iterator<Item = Tri>,
+    {
+        if let (Some(a), Some(b)) = (min_z, max_z) {
+            if a > b {
+                return Err(format!("elevation band is inverted: {a} > {b}"));
+            }
+        }
+        let boundary = Boundary::new(boundary)?;
+
+        for tri in tris {
+            let mut p = tri.to_vec();
+            if let Some(z) = min_z {
+                p = clip_z(&p, z, true);
+            }
+            if let Some(z) = max_z {
+                p = clip_z(&p, z, false);
+            }
+            if p.len() < 3 {
+                continue;
+            }
+
+            let xy = p.iter().map(|[x, y, _]| [*x, *y]).collect::<Vec<_>>();
+            let plan = poly::area2(&xy).abs() * 0.5;
+            let surf = area3(&p);
+
+            if !boundary.is_some() {
+                self.planimetric += plan;
+                self.surface += surf;
+            } else if plan > 0.0 {
+                // the slope factor is constant over a planar piece
+                let inside = boundary
+                    .clip(&xy)
+                    .iter()
+                    .map(|x| poly::area2(x).abs() * 0.5)
+                    .sum::<f64>();
+                self.planimetric += inside;
+                self.surface += inside * surf / plan;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The area of a planar 3D polygon.
+fn area3(p: &[geom::Point3]) -> f64 {
+    let n = (1..p.len().saturating_sub(1))
+        .map(|i| xprod(p[i].sub(p[0]), p[i + 1].sub(p[0])))
+        .fold(geom::Point3::zero(), |a, b| a.add(b));
+    n.mag() * 0.5
+}
+
+/// Clips a polygon to above (`keep_above`) or below the elevation `z`.
+fn clip_z(p: &[geom::Point3], z: f64, keep_above: bool) -> Vec<geom::Point3> {
+    let inside = |q: &geom::Point3| if keep_above { q[2] >= z } else { q[2] <= z };
+
+    let mut out = Vec::with_capacity(p.len() + 1);
+    for i in 0..p.len() {
+        let (a, b) = (p[i], p[(i + 1) % p.len()]);
+        if inside(&a) {
+            out.push(a);
+        }
+        if inside(&a) != inside(&b) {
+            let t = (z - a[2]) / (b[2] - a[2]);
+            out.push(a.add(b.sub(a).scale(t)));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 100 x 100 plane rising 1:1 to the east.
+    fn ramp() -> TriangleMeshSurface {
+        TriangleMeshSurface {
+            translate: Point3::zero(),
+            points: vec![0., 0., 0., 100., 0., 100., 100., 100., 100., 0., 100., 0.],
+            indices: vec![0, 1, 2, 2, 3, 0],
+        }
+    }
+
+    #[test]
+    fn mesh_areas() {
+        let m = ramp();
+        let a = m.area(&[], None, None).unwrap();
+        assert!((a.planimetric() - 10_000.0).abs() < 1e-6);
+        assert!((a.surface() - 10_000.0 * 2f64.sqrt()).abs() < 1e-6);
+
+        let a = m.area(&[], Some(25.0), Some(75.0)).unwrap();
+        assert!((a.planimetric() - 5_000.0).abs() < 1e-6);
+        assert!((a.surface() - 5_000.0 * 2f64.sqrt()).abs() < 1e-6);
+
+        let sq = [0.0, 0.0, 50.0, 0.0, 50.0, 50.0, 0.0, 50.0];
+        let a = m.area(&sq, None, Some(25.0)).unwrap();
+        assert!((a.planimetric() - 1_250.0).abs() < 1e-6);
+        assert!((a.surface() - 1_250.0 * 2f64.sqrt()).abs() < 1e-6);
+
+        assert!(m.area(&[], Some(2.0), Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn tile_areas() {
+        let world = Extents3 {
+            origin: [0.0, 0.0, 50.0].into(),
+            size: [4064.0, 4064.0, 100.0].into(),
+        };
+        // flat at 60 m, with a hole
+        let mut zs = vec![10.0 / 4064.0; COUNT.pow(2)];
+        zs[0] = f32::NAN;
+
+        let a = SurfaceArea::from_tile_zs(&world, 0, zs.clone(), &[], None, None).unwrap();
+        let full = 4064.0f64.powi(2);
+        let hole = 32.0 * 32.0 / 2.0;
+        assert!((a.planimetric() - (full - hole)).abs() < 1.0, "{a:?}");
+        assert!((a.surface() - a.planimetric()).abs() < 1e-6);
+
+        let a = SurfaceArea::from_tile_zs(&world, 0, zs, &[], Some(61.0), None).unwrap();
+        assert_eq!(a, SurfaceArea::default());
+    }
+}
@@ -0,0 +1,150 @@
+//! Colour ramps for shading surfaces by a value (usually elevation).
+use wasm_bindgen::prelude::*;
+
+/// Named ramps as evenly spaced RGB stops.
+const NAMED: &[(&str, &[u32])] = &[
+    ("greys", &[0x000000, 0xffffff]),
+    (
+        "viridis",
+        &[0x440154, 0x3b528b, 0x21918c, 0x5ec962, 0xfde725],
+    ),
+    (
+        "terrain",
+        &[0x1a9641, 0xa6d96a, 0xffffbf, 0xfdae61, 0xa6611a, 0xffffff],
+    ),
+    // diverging, red (low) to blue (high)
+    (
+        "rdbu",
+        &[
+            0xb2182b, 0xef8a62, 0xfddbc7, 0xf7f7f7, 0xd1e5f0, 0x67a9cf, 0x2166ac,
+        ],
+    ),
+];
+
+/// A ramp of value stops to RGBA colours.
+///
+/// Values below the first stop take the first colour, values above the last stop take the
+/// last colour. Between stops the colour is either linearly interpolated, or _banded_ where each
+/// stop's colour is held until the next stop.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColourRamp {
+    stops: Vec<(f64, [f32; 4])>,
+    banded: bool,
+}
+
+#[wasm_bindgen]
+impl ColourRamp {
+    /// A custom ramp.
+    ///
+    /// `values` are the stops (eg RLs in metres) in ascending order and `rgbas` are 4 floats
+    /// (0-1) per stop.
+    pub fn custom(values: &[f64], rgbas: &[f32], banded: bool) -> Result<ColourRamp, String> {
+        if values.is_empty() {
+            return Err("a colour ramp needs at least one stop".to_string());
+        }
+        if rgbas.len() != values.len() * 4 {
+            return Err(format!(
+                "expecting {} RGBA components for {} stops, found {}",
+                values.len() * 4,
+                values.len(),
+                rgbas.len()
+            ));
+        }
+        if values.iter().any(|x| !x.is_finite()) || values.windows(2).any(|x| x[0] > x[1]) {
+            return Err("colour ramp stops must be finite and ascending".to_string());
+        }
+
+        let stops = values
+            .iter()
+            .copied()
+            .zip(rgbas.chunks_exact(4))
+            .map(|(v, c)| (v, [c[0], c[1], c[2], c[3]]))
+            .collect();
+
+        Ok(Self { stops, banded })
+    }
+
+    /// A named ramp spread evenly from `min` to `max`.
+    ///
+    /// Available ramps are `greys`, `viridis`, `terrain`, and `rdbu` (diverging).
+    pub fn named(name: &str, min: f64, max: f64, banded: bool) -> Result<ColourRamp, String> {
+        let (_, hexs) = NAMED
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown colour ramp '{name}'"))?;
+
+        let n = (hexs.len() - 1) as f64;
+        let values = (0..hexs.len())
+            .map(|i| min + (max - min) * i as f64 / n)
+            .collect::<Vec<_>>();
+        let rgbas = hexs
+            .iter()
+            .flat_map(|x| {
+                let [_, r, g, b] = x.to_be_bytes();
+                [r, g, b, 255].map(|x| x as f32 / 255.0)
+            })
+            .collect::<Vec<_>>();
+
+        Self::custom(&values, &rgbas, banded)
+    }
+
+    pub fn is_banded(&self) -> bool {
+        self.banded
+    }
+}
+
+impl ColourRamp {
+    /// The colour at `value`. Non-finite values are transparent.
+    pub fn rgba(&self, value: f64) -> [f32; 4] {
+        if !value.is_finite() {
+            return [0.0; 4];
+        }
+
+        // index of the first stop above the value
+        let i = self.stops.partition_point(|(v, _)| *v <= value);
+        if i == 0 {
+            return self.stops[0].1;
+        }
+
+        let (v0, c0) = self.stops[i - 1];
+        match self.stops.get(i) {
+            Some((v1, c1)) if !self.banded => {
+                let t = ((value - v0) / (v1 - v0)) as f32;
+                std::array::from_fn(|i| c0[i] + (c1[i] - c0[i]) * t)
+            }
+            _ => c0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_and_banded() {
+        let rgbas = [0.0, 0.0, 0.0, 1.0, 1.0, 0.5, 0.0, 1.0];
+        let ramp = ColourRamp::custom(&[100.0, 200.0], &rgbas, false).unwrap();
+
+        assert_eq!(ramp.rgba(50.0), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(ramp.rgba(150.0), [0.5, 0.25, 0.0, 1.0]);
+        assert_eq!(ramp.rgba(250.0), [1.0, 0.5, 0.0, 1.0]);
+        assert_eq!(ramp.rgba(f64::NAN), [0.0; 4]);
+
+        let ramp = ColourRamp::custom(&[100.0, 200.0], &rgbas, true).unwrap();
+        assert_eq!(ramp.rgba(150.0), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(ramp.rgba(200.0), [1.0, 0.5, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn invalid_ramps() {
+        assert!(ColourRamp::custom(&[], &[], false).is_err());
+        assert!(ColourRamp::custom(&[1.0], &[0.0; 3], false).is_err());
+        assert!(ColourRamp::custom(&[2.0, 1.0], &[0.0; 8], false).is_err());
+        assert!(ColourRamp::named("nope", 0.0, 1.0, false).is_err());
+
+        let ramp = ColourRamp::named("Greys", 0.0, 10.0, false).unwrap();
+        assert_eq!(ramp.rgba(5.0), [0.5, 0.5, 0.5, 1.0]);
+    }
+}
@@ -0,0 +1,473 @@
+//! Contour line generation from tile grids.
+use super::*;
+
+/// The tolerance (world units) when joining line ends across tiles.
+const JOIN_TOL: f64 = 1e-3;
+
+/// A set of contour polylines, stored in **world** space.
+///
+/// Lines are flattened into a single points buffer, with [`Self::offsets`] giving where each
+/// line starts. Closed lines repeat their first point at the end.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct Contours {
+    lines: Vec<Line>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Line {
+    level: f64,
+    index: bool,
+    pts: Vec<geom::Point3>,
+}
+
+#[wasm_bindgen]
+impl Contours {
+    /// An empty set of contours, use [`Self::join`] to accumulate tiles into it.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Contours a tile's `zs` (render space, as stored) using marching squares.
+    ///
+    /// Contours are generated every `interval` metres, offset from `base` RL.
+    /// Every `index_every`th contour (counting from `base`) is tagged as an _index_ contour,
+    /// use 0 to not tag any.
+    pub fn from_tile_zs(
+        extents: &Extents3,
+        tile_idx: u32,
+        zs: &[f32],
+        interval: f64,
+        base: f64,
+        index_every: u32,
+    ) -> Result<Contours, String> {
+        init_panic_hook();
+
+        if zs.len() != COUNT.pow(2) {
+            return Err(format!(
+                "expecting {} tile zs, found {}",
+                COUNT.pow(2),
+                zs.len()
+            ));
+        }
+        if !interval.is_finite() || interval <= 0.0 || !base.is_finite() {
+            return Err("contour interval must be positive".to_string());
+        }
+
+        let tile = TileId::from_num(tile_idx);
+        let origin = tile.extents(extents).origin;
+        let res = tile.lod_res();
+        let (scaler, z0) = (extents.max_dim(), extents.origin.z);
+
+        // world RLs
+        let zs = zs
+            .iter()
+            .map(|z| *z as f64 * scaler + z0)
+            .collect::<Vec<_>>();
+
+        let (min, max) = zs
+            .iter()
+            .filter(|z| z.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(a, b), z| {
+                (a.min(*z), b.max(*z))
+            });
+        if min > max {
+            return Ok(Self::default()); // no data
+        }
+
+        let k0 = ((min - base) / interval).ceil() as i64;
+        let k1 = ((max - base) / interval).floor() as i64;
+        if k1 - k0 > 10_000 {
+            return Err(format!("contour interval {interval} is too fine"));
+        }
+
+        let pt = |i: usize| {
+            let (x, y) = (i % COUNT, i / COUNT);
+            [x, y].map(|x| x as f64 * res).add(origin).with_z(zs[i])
+        };
+
+        let mut lines = Vec::new();
+        for k in k0..=k1 {
+            let level = base + k as f64 * interval;
+            let index = index_every > 0 && k.rem_euclid(index_every as i64) == 0;
+
+            for pts in march(&zs, level) {
+                // interpolate the crossing along each edge
+                let pts = pts
+                    .into_iter()
+                    .map(|(a, b)| {
+                        let (pa, pb) = (pt(a), pt(b));
+                        let t = (level - pa[2]) / (pb[2] - pa[2]);
+                        pa.add(pb.sub(pa).scale(t))
+                    })
+                    .collect();
+                lines.push(Line { level, index, pts });
+            }
+        }
+
+        Ok(Self { lines })
+    }
+
+    /// Adds the lines of `other` (usually a neighbouring tile), joining any lines at the same
+    /// level whose ends meet into continuous lines.
+    pub fn join(&mut self, other: &Contours) {
+        let mut lines = self
+            .lines
+            .drain(..)
+            .chain(other.lines.iter().cloned())
+            .map(Some)
+            .collect::<Vec<_>>();
+
+        // the open lines by their quantised end points, entries are checked against the line's
+        // current ends when looked up (so merged away ends are left in place)
+        let mut ends = HashMap::<(u64, [i64; 2]), Vec<usize>>::default();
+        let cell = |level: f64, [x, y, _]: geom::Point3| {
+            (
+                level.to_bits(),
+                [x, y].map(|x| (x / JOIN_TOL).floor() as i64),
+            )
+        };
+        for (i, x) in lines.iter().enumerate() {
+            let Some(x) = x.as_ref().filter(|x| !x.is_closed()) else { continue; };
+            let (a, b) = x.ends();
+            for p in [a, b] {
+                ends.entry(cell(x.level, p)).or_default().push(i);
+            }
+        }
+
+        let close = |a: geom::Point3, b: geom::Point3| a.sub(b).mag() < JOIN_TOL;
+        for i in 0..lines.len() {
+            while let Some(a) = lines[i].as_ref().filter(|x| !x.is_closed()) {
+                let (a0, a1) = a.ends();
+
+                // an open line at the same level with an end (within the tolerance) at either end
+                let meets = |j: usize| {
+                    lines[j].as_ref().is_some_and(|b| {
+                        let (b0, b1) = b.ends();
+                        j != i
+                            && !b.is_closed()
+                            && [a0, a1].iter().any(|p| close(*p, b0) || close(*p, b1))
+                    })
+                };
+                let found = [a0, a1].into_iter().find_map(|p| {
+                    let (lvl, [x, y]) = cell(a.level, p);
+                    (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (lvl, [x + dx, y + dy])))
+                        .filter_map(|k| ends.get(&k))
+                        .flatten()
+                        .copied()
+                        .find(|j| meets(*j))
+                });
+                let Some(j) = found else { break; };
+
+                let mut b = lines[j].take().expect("a line");
+                let a = lines[i].as_mut().expect("a line");
+                let (b0, b1) = b.ends();
+
+                // orient both lines to run a -> b
+                if close(a1, b0) {
+                    // already oriented
+                } else if close(a1, b1) {
+                    b.pts.reverse();
+                } else if close(a0, b1) {
+                    a.pts.reverse();
+                    b.pts.reverse();
+                } else {
+                    a.pts.reverse();
+                }
+                a.pts.extend(b.pts.into_iter().skip(1));
+
+                // snap the ends together if the line is now closed
+                let (a0, a1) = a.ends();
+                if close(a0, a1) {
+                    *a.pts.last_mut().expect("not empty") = a0;
+                } else {
+                    // keep trying to extend this line
+                    for p in [a0, a1] {
+                        ends.entry(cell(a.level, p)).or_default().push(i);
+                    }
+                }
+            }
+        }
+
+        self.lines = lines.into_iter().flatten().collect();
+    }
+
+    /// The number of lines.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The RL of each line.
+    pub fn levels(&self) -> Vec<f64> {
+        self.lines.iter().map(|x| x.level).collect()
+    }
+
+    /// `1` for each line which is an index contour, otherwise `0`.
+    pub fn index_flags(&self) -> Vec<u8> {
+        self.lines.iter().map(|x| u8::from(x.index)).collect()
+    }
+
+    /// The offset (in points) each line starts at, with a trailing total.
+    pub fn offsets(&self) -> Vec<u32> {
+        let mut n = 0;
+        let mut offsets = vec![0];
+        for x in &self.lines {
+            n += x.pts.len() as u32;
+            offsets.push(n);
+        }
+        offsets
+    }
+
+    /// The line points (x,y,z) in **world** space.
+    pub fn points(&self) -> Vec<f64> {
+        self.lines
+            .iter()
+            .flat_map(|x| x.pts.iter().copied().flatten())
+            .collect()
+    }
+
+    /// The line points (x,y,z) in **render** space (_Y-up_) for the given data extents.
+    pub fn render_points(&self, extents: &Extents3) -> Vec<f32> {
+        self.lines
+            .iter()
+            .flat_map(|x| x.pts.iter())
+            .flat_map(|p| extents.to_render(*p).map(|x| x as f32))
+            .collect()
+    }
+}
+
+impl Line {
+    fn ends(&self) -> (geom::Point3, geom::Point3) {
+        (self.pts[0], self.pts[self.pts.len() - 1])
+    }
+
+    fn is_closed(&self) -> bool {
+        let (a, b) = self.ends();
+        self.pts.len() > 2 && a == b
+    }
+}
+
+/// The cell edges a segment crosses for each marching squares case.
+///
+/// Edges are 0 bottom, 1 right, 2 top, 3 left. Corner bits are bottom-left 1, bottom-right 2,
+/// top-right 4, top-left 8. The saddles (5, 10) are resolved separately.
+const CASES: [&[[u8; 2]]; 16] = [
+    &[],
+    &[[3, 0]],
+    &[[0, 1]],
+    &[[3, 1]],
+    &[[1, 2]],
+    &[], // saddle
+    &[[0, 2]],
+    &[[3, 2]],
+    &[[2, 3]],
+    &[[0, 2]],
+    &[], // saddle
+    &[[1, 2]],
+    &[[3, 1]],
+    &[[0, 1]],
+    &[[3, 0]],
+    &[],
+];
+
+/// Runs marching squares over the grid at `level`, returning the chained lines as the sample
+/// pairs whose edge each point crosses.
+fn march(zs: &[f64], level: f64) -> Vec<Vec<(usize, usize)>> {
+    let size = COUNT - 1;
+
+    // an edge is keyed by its sample pair (lower index first)
+    let mut segs: Vec<[(usize, usize); 2]> = Vec::new();
+    for y in 0..size {
+        for x in 0..size {
+            let bl = y * COUNT + x;
+            let br = bl + 1;
+            let tl = bl + COUNT;
+            let tr = tl + 1;
+            let corners = [bl, br, tr, tl].map(|i| zs[i]);
+            if corners.iter().any(|z| !z.is_finite()) {
+                continue;
+            }
+
+            let case = corners
+                .iter()
+                .enumerate()
+                .fold(0, |c, (i, z)| c | (usize::from(*z >= level) << i));
+            let edges = [(bl, br), (br, tr), (tl, tr), (bl, tl)];
+
+            let centre_above = corners.iter().sum::<f64>() * 0.25 >= level;
+            let pairs: &[[u8; 2]] = match (case, centre_above) {
+                (5, true) | (10, false) => &[[0, 1], [2, 3]],
+                (5, false) | (10, true) => &[[3, 0], [1, 2]],
+                _ => CASES[case],
+            };
+
+            segs.extend(
+                pairs
+                    .iter()
+                    .map(|[a, b]| [edges[*a as usize], edges[*b as usize]]),
+            );
+        }
+    }
+
+    chain(segs)
+}
+
+/// Chains segments sharing an edge into lines.
+fn chain(segs: Vec<[(usize, usize); 2]>) -> Vec<Vec<(usize, usize)>> {
+    let mut adj: HashMap<(usize, usize), Vec<usize>> = HashMap::default();
+    for (i, [a, b]) in segs.iter().enumerate() {
+        adj.entry(*a).or_default().push(i);
+        adj.entry(*b).or_default().push(i);
+    }
+
+    let mut used = vec![false; segs.len()];
+    let mut lines = Vec::new();
+
+    // open lines start from an edge with a single segment, then the remaining are loops
+    let starts = segs
+        .iter()
+        .enumerate()
+        .flat_map(|(i, s)| s.iter().map(move |e| (i, *e)))
+        .filter(|(_, e)| adj[e].len() == 1)
+        .chain(segs.iter().enumerate().map(|(i, s)| (i, s[0])))
+        .collect::<Vec<_>>();
+
+    for (s, e) in starts {
+        if used[s] {
+            continue;
+        }
+
+        let mut line = vec![e];
+        let (mut seg, mut at) = (s, e);
+        loop {
+            used[seg] = true;
+            let [a, b] = segs[seg];
+            at = if a == at { b } else { a };
+            line.push(at);
+
+            match adj[&at].iter().find(|i| !used[**i]) {
+                Some(i) => seg = *i,
+                None => break,
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> Extents3 {
+        Extents3 {
+            origin: [0.0, 0.0, 1000.0].into(),
+            size: [8128.0, 4064.0, 100.0].into(),
+        }
+    }
+
+    /// A cone centred at `c` (world xy), render space zs.
+    fn cone(tile: TileId, c: Point2) -> Vec<f32> {
+        let xs = tile.extents(&world());
+        (0..COUNT.pow(2))
+            .map(|i| {
+                let p = [(i % COUNT) as f64, (i / COUNT) as f64]
+                    .scale(tile.lod_res())
+                    .add(xs.origin);
+                let rl = 100.0 - p.sub(c).mag() * 0.05;
+                (rl / 8128.0) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closed_rings_on_a_cone() {
+        let tile = TileId { root: 0, path: 0 };
+        let zs = cone(tile, [2032.0, 2032.0]);
+
+        let c = Contours::from_tile_zs(&world(), tile.as_num(), &zs, 10.0, 1000.0, 5).unwrap();
+        assert!(!c.is_empty());
+        for line in &c.lines {
+            assert!(line.pts.iter().all(|p| (p[2] - line.level).abs() < 1e-6));
+        }
+
+        // the 1090 ring is fully inside the tile (radius 200 m)
+        let ring = c.lines.iter().find(|x| x.level == 1090.0).unwrap();
+        assert!(ring.is_closed());
+        assert_eq!(c.lines.iter().filter(|x| x.level == 1090.0).count(), 1);
+
+        // every 50 m from the base is an index contour
+        let flags = c.index_flags();
+        for (l, f) in c.levels().into_iter().zip(flags) {
+            assert_eq!(f == 1, (l - 1000.0) % 50.0 == 0.0, "{l}");
+        }
+
+        assert_eq!(*c.offsets().last().unwrap() as usize * 3, c.points().len());
+        assert!(Contours::from_tile_zs(&world(), 0, &zs, 0.0, 0.0, 0).is_err());
+    }
+
+    #[test]
+    fn joining_across_tiles() {
+        // cone centred on the boundary of the two root tiles
+        let (a, b) = (TileId { root: 0, path: 0 }, TileId { root: 1, path: 0 });
+        let c = [4064.0, 2032.0];
+        let level = |c: &Contours| {
+            c.lines
+                .iter()
+                .filter(|x| x.level == 1090.0)
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let mut x = Contours::new();
+        x.join(
+            &Contours::from_tile_zs(&world(), a.as_num(), &cone(a, c), 10.0, 1000.0, 0).unwrap(),
+        );
+        assert_eq!(level(&x).len(), 1);
+        assert!(!level(&x)[0].is_closed());
+
+        x.join(
+            &Contours::from_tile_zs(&world(), b.as_num(), &cone(b, c), 10.0, 1000.0, 0).unwrap(),
+        );
+        let ring = level(&x);
+        assert_eq!(ring.len(), 1);
+        assert!(ring[0].is_closed());
+    }
+
+    #[test]
+    fn joining_many_pieces() {
+        // a long line at each level, cut into pieces which are shuffled and some reversed
+        let n = 2000;
+        let mut pieces = Vec::new();
+        for level in [10.0, 20.0] {
+            for i in 0..n {
+                let p = |i: usize| [i as f64 * 0.5, level, level];
+                let mut pts = vec![p(i), p(i + 1)];
+                if i % 3 == 0 {
+                    pts.reverse();
+                }
+                pieces.push(Line {
+                    level,
+                    index: false,
+                    pts,
+                });
+            }
+        }
+        let mut order = (0..pieces.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|i| (i * 7919) % pieces.len());
+        let lines = order.into_iter().map(|i| pieces[i].clone()).collect();
+
+        let mut c = Contours::new();
+        c.join(&Contours { lines });
+        assert_eq!(c.len(), 2);
+        assert!(c.lines.iter().all(|x| x.pts.len() == n + 1));
+        assert!(c.lines.iter().all(|x| !x.is_closed()));
+    }
+}
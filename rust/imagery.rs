@@ -0,0 +1,167 @@
+//! Georeferenced imagery for draping over surfaces.
+use super::*;
+
+/// A georeferenced image (eg an orthophoto) covering an extent in **world** XY.
+///
+/// Pixel rows run from the top (north) down, as is the convention for image files.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GeoImage {
+    extents: Extents2,
+    width: u32,
+    height: u32,
+}
+
+/// A window into an image, in pixels from the top-left.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PixelWindow {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[wasm_bindgen]
+impl GeoImage {
+    /// An image of `width` x `height` pixels covering the world XY extents.
+    pub fn new(
+        min_x: f64,
+        min_y: f64,
+        max_x: f64,
+        max_y: f64,
+        width: u32,
+        height: u32,
+    ) -> Result<GeoImage, String> {
+        if !(min_x < max_x && min_y < max_y) {
+            return Err("image extents must have a positive size".to_string());
+        }
+        if width == 0 || height == 0 {
+            return Err("image must have at least one pixel".to_string());
+        }
+
+        Ok(Self {
+            extents: Extents2 {
+                origin: [min_x, min_y],
+                size: [max_x - min_x, max_y - min_y],
+            },
+            width,
+            height,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The pixel window of the image which covers a tile, or `undefined` if there is no overlap.
+    ///
+    /// The window is expanded to whole pixels, so use [`Self::crop`] to get the georeference of
+    /// the cut image.
+    pub fn tile_window(&self, extents: &Extents3, tile_idx: u32) -> Option<PixelWindow> {
+        let tile = TileId::from_num(tile_idx).extents(extents);
+        let [px, py] = self.pixel_size();
+        let [x0, _] = self.extents.origin;
+        let [_, y1] = self.extents.max();
+        let [tx0, ty0] = tile.origin;
+        let [tx1, ty1] = tile.max();
+
+        let clamp = |x: f64, max: u32| x.max(0.0).min(max as f64) as u32;
+        let c0 = clamp(((tx0 - x0) / px).floor(), self.width);
+        let c1 = clamp(((tx1 - x0) / px).ceil(), self.width);
+        // rows run down from the top
+        let r0 = clamp(((y1 - ty1) / py).floor(), self.height);
+        let r1 = clamp(((y1 - ty0) / py).ceil(), self.height);
+
+        (c0 < c1 && r0 < r1).then_some(PixelWindow {
+            x: c0,
+            y: r0,
+            width: c1 - c0,
+            height: r1 - r0,
+        })
+    }
+
+    /// The georeference of the image cut to `window`.
+    pub fn crop(&self, window: &PixelWindow) -> GeoImage {
+        let [px, py] = self.pixel_size();
+        let [x0, _] = self.extents.origin;
+        let [_, y1] = self.extents.max();
+        let PixelWindow {
+            x,
+            y,
+            width,
+            height,
+        } = *window;
+
+        Self {
+            extents: Extents2 {
+                origin: [x0 + x as f64 * px, y1 - (y + height) as f64 * py],
+                size: [width as f64 * px, height as f64 * py],
+            },
+            width,
+            height,
+        }
+    }
+}
+
+impl GeoImage {
+    fn pixel_size(&self) -> Point2 {
+        let [w, h] = self.extents.size;
+        [w / self.width as f64, h / self.height as f64]
+    }
+
+    /// The texture coordinate of a world XY point, with `v` running up from the bottom (south).
+    pub fn uv(&self, p: Point2) -> [f32; 2] {
+        let [u, v] = p.sub(self.extents.origin);
+        let [w, h] = self.extents.size;
+        [(u / w) as f32, (v / h) as f32]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_window_and_crop() {
+        let world = Extents3 {
+            origin: [1000.0, 2000.0, 0.0].into(),
+            size: [8128.0, 4064.0, 100.0].into(),
+        };
+        // 1 m pixels over the whole world
+        let img = GeoImage::new(1000.0, 2000.0, 9128.0, 6064.0, 8128, 4064).unwrap();
+
+        // second root tile is the east half
+        let tile = TileId { root: 1, path: 0 }.as_num();
+        let w = img.tile_window(&world, tile).unwrap();
+        assert_eq!(
+            w,
+            PixelWindow {
+                x: 4064,
+                y: 0,
+                width: 4064,
+                height: 4064
+            }
+        );
+
+        let crop = img.crop(&w);
+        assert_eq!(crop.uv([5064.0, 2000.0]), [0.0, 0.0]);
+        assert_eq!(crop.uv([9128.0, 6064.0]), [1.0, 1.0]);
+
+        // the south-west child of the first root tile sits in the bottom rows
+        let tile = TileId {
+            root: 0,
+            path: 0b001_0000_0000_0000_0,
+        }
+        .as_num();
+        let w = img.tile_window(&world, tile).unwrap();
+        assert_eq!((w.x, w.y, w.width, w.height), (0, 2032, 2032, 2032));
+
+        let outside = GeoImage::new(0.0, 0.0, 10.0, 10.0, 10, 10).unwrap();
+        assert_eq!(outside.tile_window(&world, tile), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Per-tile metadata: height ranges and valid node counts.
+use super::*;
+
+/// A tile's sampled grid along with its height range and number of valid nodes.
+///
+/// Heights are in **stored, render space** (as the `zs`). The range is `NaN` if there are
+/// no valid nodes.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct TileSample {
+    zs: Vec<f32>,
+    min_z: f32,
+    max_z: f32,
+    valid: u32,
+}
+
+#[wasm_bindgen]
+impl TileSample {
+    /// Summarise a tile's `zs`.
+    pub fn from_zs(zs: Vec<f32>) -> Result<TileSample, String> {
+        let ([min_z, max_z], valid) = summarise(&zs)?;
+        Ok(Self {
+            zs,
+            min_z,
+            max_z,
+            valid,
+        })
+    }
+
+    pub fn zs(&self) -> Vec<f32> {
+        self.zs.clone()
+    }
+
+    pub fn min_z(&self) -> f32 {
+        self.min_z
+    }
+
+    pub fn max_z(&self) -> f32 {
+        self.max_z
+    }
+
+    /// The number of non-`NaN` nodes.
+    pub fn valid(&self) -> u32 {
+        self.valid
+    }
+}
+
+impl TileSample {
+    pub(crate) fn into_zs(self) -> Vec<f32> {
+        self.zs
+    }
+
+    /// The **world** z range, `None` if there are no valid nodes.
+    pub(crate) fn world_range(&self, extents: &Extents3) -> Option<[f64; 2]> {
+        range_to_world(extents, [self.min_z, self.max_z])
+    }
+}
+
+/// A compact index of an object's tiles, recording each tile's height range and valid node
+/// count.
+///
+/// The index is kept alongside an object's tiles so the viewer and picker can bound tiles
+/// without loading them.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TileIndex {
+    /// (tile, stored z range, valid nodes), sorted by tile
+    entries: Vec<(u32, [f32; 2], u16)>,
+}
+
+#[wasm_bindgen]
+impl TileIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert (or replace) a tile's metadata.
+    pub fn insert(&mut self, tile_idx: u32, sample: &TileSample) {
+        self.insert_entry((
+            tile_idx,
+            [sample.min_z, sample.max_z],
+            sample.valid.min(u16::MAX as u32) as u16,
+        ));
+    }
+
+    /// Insert (or replace) a tile's metadata straight from its `zs`, without keeping a
+    /// [`TileSample`] (and its copy of the zs) around.
+    pub fn insert_zs(&mut self, tile_idx: u32, zs: &[f32]) -> Result<(), String> {
+        let (range, valid) = summarise(zs)?;
+        self.insert_entry((tile_idx, range, valid.min(u16::MAX as u32) as u16));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, tile_idx: u32) {
+        if let Ok(i) = self.entries.binary_search_by_key(&tile_idx, |x| x.0) {
+            self.entries.remove(i);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The indexed tiles (sorted).
+    pub fn tiles(&self) -> Vec<u32> {
+        self.entries.iter().map(|x| x.0).collect()
+    }
+
+    /// The tile's (stored) minimum z, `undefined` if not indexed.
+    pub fn min_z(&self, tile_idx: u32) -> Option<f32> {
+        self.get(tile_idx).map(|x| x.1[0])
+    }
+
+    /// The tile's (stored) maximum z, `undefined` if not indexed.
+    pub fn max_z(&self, tile_idx: u32) -> Option<f32> {
+        self.get(tile_idx).map(|x| x.1[1])
+    }
+
+    /// The tile's number of valid nodes, `undefined` if not indexed.
+    pub fn valid(&self, tile_idx: u32) -> Option<u32> {
+        self.get(tile_idx).map(|x| x.2 as u32)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        Store::to_bytes(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<TileIndex, String> {
+        Store::from_bytes(bytes)
+    }
+}
+
+impl TileIndex {
+    fn insert_entry(&mut self, e: (u32, [f32; 2], u16)) {
+        match self.entries.binary_search_by_key(&e.0, |x| x.0) {
+            Ok(i) => self.entries[i] = e,
+            Err(i) => self.entries.insert(i, e),
+        }
+    }
+
+    fn get(&self, tile_idx: u32) -> Option<&(u32, [f32; 2], u16)> {
+        self.entries
+            .binary_search_by_key(&tile_idx, |x| x.0)
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    /// An index of tiles without height ranges.
+    pub(crate) fn from_tiles(tiles: &[u32]) -> Self {
+        let mut entries = tiles
+            .iter()
+            .map(|t| (*t, [f32::NAN; 2], 0))
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|x| x.0);
+        entries.dedup_by_key(|x| x.0);
+        Self { entries }
+    }
+
+    /// Accumulates the **world** z range of each tile's subtree, for the indexed tiles and
+    /// all their ancestors.
+    ///
+    /// Tiles without a range take the whole world z range.
+    pub(crate) fn subtree_ranges(&self, extents: &Extents3, out: &mut HashMap<u32, [f64; 2]>) {
+        let world = [extents.origin.z, extents.origin.z + extents.size.z];
+        for (tile, range, _) in &self.entries {
+            let [z0, z1] = range_to_world(extents, *range).unwrap_or(world);
+            let mut t = Some(TileId::from_num(*tile));
+            while let Some(x) = t {
+                let r = out
+                    .entry(x.as_num())
+                    .or_insert([f64::INFINITY, f64::NEG_INFINITY]);
+                if r[0] <= z0 && r[1] >= z1 {
+                    break; // the ancestors already cover this range
+                }
+                *r = [r[0].min(z0), r[1].max(z1)];
+                t = x.parent();
+            }
+        }
+    }
+}
+
+impl Store for TileIndex {
+    /// Serialize the index into binary data.
+    ///
+    /// # Format
+    /// **All encoding is done in Big Endian.**
+    /// ```plaintext
+    /// 8 bytes: entries.len()    (u64)
+    /// for n in 0..entries.len()
+    ///     4 bytes: tile index   (u32)
+    ///     4 bytes: min z        (f32)
+    ///     4 bytes: max z        (f32)
+    ///     2 bytes: valid nodes  (u16)
+    /// ```
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(8 + self.entries.len() * 14);
+        buf.extend((self.entries.len() as u64).to_be_bytes());
+        for (tile, [a, b], valid) in &self.entries {
+            buf.extend(tile.to_be_bytes());
+            buf.extend(a.to_be_bytes());
+            buf.extend(b.to_be_bytes());
+            buf.extend(valid.to_be_bytes());
+        }
+
+        buf
+    }
+
+    /// Deserialize an index from binary data.
+    ///
+    /// See [`Self::to_bytes`] for the format.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut d = StoreDecoder::new(bytes);
+
+        let n = d.u64()? as usize;
+        let mut entries = Vec::with_capacity(n.min(bytes.len() / 14));
+        for _ in 0..n {
+            let tile = d.u32()?;
+            let range = [d.f32()?, d.f32()?];
+            let valid = d.u16()?;
+            entries.push((tile, range, valid));
+        }
+        entries.sort_unstable_by_key(|x| x.0);
+
+        Ok(Self { entries })
+    }
+}
+
+/// The (min, max) z and number of valid nodes of a tile's `zs`, the range is `NaN` if there
+/// are no valid nodes.
+fn summarise(zs: &[f32]) -> Result<([f32; 2], u32), String> {
+    if zs.len() != COUNT.pow(2) {
+        return Err(format!("expecting {} tile zs", COUNT.pow(2)));
+    }
+
+    let (mut min_z, mut max_z, mut valid) = (f32::INFINITY, f32::NEG_INFINITY, 0);
+    for z in zs.iter().filter(|z| z.is_finite()) {
+        min_z = min_z.min(*z);
+        max_z = max_z.max(*z);
+        valid += 1;
+    }
+    if valid == 0 {
+        (min_z, max_z) = (f32::NAN, f32::NAN);
+    }
+
+    Ok(([min_z, max_z], valid))
+}
+
+/// Converts a stored z range to world, `None` if it is not finite.
+fn range_to_world(extents: &Extents3, [a, b]: [f32; 2]) -> Option<[f64; 2]> {
+    let scaler = extents.max_dim();
+    let z0 = extents.origin.z;
+    (a.is_finite() && b.is_finite()).then_some([a as f64 * scaler + z0, b as f64 * scaler + z0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_summary() {
+        let mut zs = vec![f32::NAN; COUNT.pow(2)];
+        zs[3] = 0.25;
+        zs[7] = 0.5;
+        let s = TileSample::from_zs(zs).unwrap();
+        assert_eq!((s.min_z(), s.max_z(), s.valid()), (0.25, 0.5, 2));
+
+        let world = Extents3 {
+            origin: [0.0, 0.0, 100.0].into(),
+            size: [4.0, 4.0, 4.0].into(),
+        };
+        assert_eq!(s.world_range(&world), Some([101.0, 102.0]));
+
+        let s = TileSample::from_zs(vec![f32::NAN; COUNT.pow(2)]).unwrap();
+        assert_eq!(s.valid(), 0);
+        assert!(s.min_z().is_nan() && s.world_range(&world).is_none());
+        assert!(TileSample::from_zs(vec![0.0; 3]).is_err());
+    }
+
+    #[test]
+    fn index_roundtrip_and_ranges() {
+        let world = Extents3 {
+            origin: [0.0, 0.0, 0.0].into(),
+            size: [4064.0, 4064.0, 4064.0].into(),
+        };
+        let flat = |z: f32| TileSample::from_zs(vec![z; COUNT.pow(2)]).unwrap();
+        let [a, b, ..] = TileId::from_num(0).children();
+
+        let mut idx = TileIndex::new();
+        idx.insert(b.as_num(), &flat(0.5));
+        idx.insert(a.as_num(), &flat(0.25));
+        idx.insert(0, &flat(0.3));
+        assert_eq!(idx.tiles(), vec![0, a.as_num(), b.as_num()]);
+        assert_eq!(idx.valid(a.as_num()), Some(COUNT.pow(2) as u32));
+        assert_eq!(idx.min_z(7), None);
+
+        // inserting straight from the zs matches inserting the sample
+        let mut direct = idx.clone();
+        direct.insert_zs(a.as_num(), &[0.25; COUNT.pow(2)]).unwrap();
+        assert_eq!(direct, idx);
+        assert!(direct.insert_zs(0, &[0.0; 3]).is_err());
+
+        let back = TileIndex::from_bytes(&idx.to_bytes()).unwrap();
+        assert_eq!(back, idx);
+        assert!(TileIndex::from_bytes(&idx.to_bytes()[..20]).is_err());
+
+        // the root covers both children
+        let mut rs = HashMap::default();
+        idx.subtree_ranges(&world, &mut rs);
+        assert_eq!(rs[&0], [1016.0, 2032.0]);
+        assert_eq!(rs[&a.as_num()], [1016.0, 1016.0]);
+
+        idx.remove(0);
+        assert_eq!(idx.len(), 2);
+    }
+}
@@ -0,0 +1,168 @@
+//! Difference (isopach) surfaces derived from the tiles of two objects.
+use super::*;
+
+/// Builds the tiles of the difference (isopach) surface _A_ less _B_.
+///
+/// The tiles are stored like any other surface's, where the _world_ elevation of the
+/// difference surface is the thickness above the [`Self::datum`] (so a diverging
+/// [`ColourRamp`] centred on the datum shows the fill and cut).
+///
+/// The tile culling assumes surfaces lie within the data extents, so the datum is chosen from
+/// the thickness range: first [`Self::include`] every pair of tiles, then build the tiles with
+/// [`Self::tile_zs`]. If the thickness range is larger than the z extents it cannot fit, which
+/// [`Self::fits`] reports.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug)]
+pub struct Isopach {
+    origin_z: f64,
+    size_z: f64,
+    scaler: f64,
+    /// thickness range in render units, `NaN` if nothing is included
+    range: [f32; 2],
+}
+
+#[wasm_bindgen]
+impl Isopach {
+    pub fn new(extents: &Extents3) -> Self {
+        Self {
+            origin_z: extents.origin.z,
+            size_z: extents.size.z,
+            scaler: extents.max_dim(),
+            range: [f32::NAN; 2],
+        }
+    }
+
+    /// Widens the thickness range by matching tiles (stored, render space) of the two objects.
+    pub fn include(&mut self, a_zs: &[f32], b_zs: &[f32]) -> Result<(), String> {
+        check_tiles(a_zs, b_zs)?;
+        let [min, max] = &mut self.range;
+        for (a, b) in a_zs.iter().zip(b_zs) {
+            let d = a - b;
+            if d.is_finite() {
+                // NaN loses to any number
+                *min = min.min(d);
+                *max = max.max(d);
+            }
+        }
+        Ok(())
+    }
+
+    /// The world elevation of zero thickness.
+    ///
+    /// The included thickness range is centred on the middle of the world z range. With nothing
+    /// included, this is the middle of the world z range.
+    pub fn datum(&self) -> f64 {
+        self.origin_z + self.shift() as f64 * self.scaler
+    }
+
+    /// If the included thickness range fits within the world z range.
+    pub fn fits(&self) -> bool {
+        let [min, max] = self.range;
+        min.is_nan() || (max - min) as f64 * self.scaler <= self.size_z
+    }
+
+    /// The difference tile zs of matching tiles (stored, render space) of the two objects.
+    ///
+    /// `NaN` is kept wherever either side is missing, and `undefined` is returned if the tiles
+    /// do not overlap at all.
+    pub fn tile_zs(&self, a_zs: &[f32], b_zs: &[f32]) -> Result<Option<Vec<f32>>, String> {
+        check_tiles(a_zs, b_zs)?;
+        let shift = self.shift();
+        let zs = a_zs
+            .iter()
+            .zip(b_zs)
+            .map(|(a, b)| a - b + shift)
+            .collect::<Vec<_>>();
+
+        Ok(zs.iter().any(|z| z.is_finite()).then_some(zs))
+    }
+}
+
+impl Isopach {
+    /// The datum's render z, render z being (z - origin.z) / scaler.
+    fn shift(&self) -> f32 {
+        let mid = (self.size_z * 0.5 / self.scaler) as f32;
+        let [min, max] = self.range;
+        if min.is_nan() {
+            mid
+        } else {
+            mid - (min + max) * 0.5
+        }
+    }
+}
+
+fn check_tiles(a_zs: &[f32], b_zs: &[f32]) -> Result<(), String> {
+    if a_zs.len() != COUNT.pow(2) || b_zs.len() != COUNT.pow(2) {
+        Err(format!("expecting {} tile zs", COUNT.pow(2)))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> Extents3 {
+        Extents3 {
+            origin: [0.0, 0.0, 100.0].into(),
+            size: [4064.0, 4064.0, 100.0].into(),
+        }
+    }
+
+    #[test]
+    fn difference_tiles() {
+        let world = world();
+        let scaler = world.max_dim() as f32;
+        let mut a = vec![5.0 / scaler; COUNT.pow(2)];
+        let b = vec![2.0 / scaler; COUNT.pow(2)];
+        a[0] = f32::NAN;
+
+        let mut iso = Isopach::new(&world);
+        assert!((iso.datum() - 150.0).abs() < 1e-3);
+        iso.include(&a, &b).unwrap();
+        assert!(iso.fits());
+
+        let d = iso.tile_zs(&a, &b).unwrap().unwrap();
+        assert!(d[0].is_nan());
+        // the world elevation of the difference is the thickness above the datum, which is
+        // within the world z range
+        let [_, _, z] = geom::Point3::from(world.render_to_world(0.0, d[1] as f64, 0.0));
+        assert!((z - iso.datum() - 3.0).abs() < 1e-3, "{z}");
+        assert!(z > world.origin.z && z < world.origin.z + world.size.z);
+
+        let none = vec![f32::NAN; COUNT.pow(2)];
+        assert_eq!(iso.tile_zs(&none, &b), Ok(None));
+        assert!(iso.tile_zs(&[0.0], &b).is_err());
+        assert!(iso.include(&[0.0], &b).is_err());
+    }
+
+    #[test]
+    fn datum_from_thickness_range() {
+        let world = world();
+        let scaler = world.max_dim() as f32;
+        // thicknesses of 40 to 80, beyond half the 100 z range
+        let mut a = vec![80.0 / scaler; COUNT.pow(2)];
+        a[1] = 40.0 / scaler;
+        let b = vec![0.0; COUNT.pow(2)];
+
+        let mut iso = Isopach::new(&world);
+        iso.include(&a, &b).unwrap();
+        assert!(iso.fits());
+        let d = iso.tile_zs(&a, &b).unwrap().unwrap();
+        for z in [d[0], d[1]] {
+            let [_, _, z] = geom::Point3::from(world.render_to_world(0.0, z as f64, 0.0));
+            assert!(
+                z >= world.origin.z && z <= world.origin.z + world.size.z,
+                "{z}"
+            );
+        }
+        // the range is centred in the world z range
+        assert!((iso.datum() - 90.0).abs() < 1e-3, "{}", iso.datum());
+
+        // a thickness range over the z range cannot fit
+        a[2] = -40.0 / scaler;
+        iso.include(&a, &b).unwrap();
+        assert!(!iso.fits());
+    }
+}
@@ -5,10 +5,36 @@ extern crate quickcheck;
 extern crate quickcheck_macros;
 
 use geom::*;
-use rustc_hash::FxHashMap as HashMap;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
 use wasm_bindgen::prelude::*;
 use web_sys::console;
 
+mod area;
+mod colour;
+mod contour;
+mod imagery;
+mod index;
+mod isopach;
+mod measure;
+mod pick;
+mod poly;
+mod raster;
+mod rtin;
+mod section;
+mod volume;
+
+pub use area::SurfaceArea;
+pub use colour::ColourRamp;
+pub use contour::Contours;
+pub use imagery::{GeoImage, PixelWindow};
+pub use index::{TileIndex, TileSample};
+pub use isopach::Isopach;
+pub use measure::Measurement;
+pub use pick::{PickHit, Picker};
+pub use raster::TerrainGrid;
+pub use section::{Section, SectionSampler};
+pub use volume::VolumeReport;
+
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
@@ -74,6 +100,13 @@ pub struct Point3 {
     pub z: f64,
 }
 
+#[wasm_bindgen]
+impl Point3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+}
+
 impl Point3 {
     pub fn zero() -> Self {
         geom::Point3::zero().into()
@@ -138,6 +171,38 @@ impl Extents3 {
             .into()
     }
 
+    /// Transforms a _world_ coordinate into a _render_ coordinate.
+    ///
+    /// This is the inverse of [`Self::render_to_world`]: the world coordinate is in Z-up and the
+    /// returned render coordinate is in Y-up (so y/z will be swapped.)
+    pub fn world_to_render(&self, x: f64, y: f64, z: f64) -> Point3 {
+        self.to_render([x, y, z]).into()
+    }
+
+    /// Transforms a flat list of _render_ coordinates (x,y,z triples) into _world_ coordinates.
+    pub fn render_to_world_flat(&self, xyzs: &[f64]) -> Result<Vec<f64>, String> {
+        let pts = xyzs.chunks_exact(3);
+        if !pts.remainder().is_empty() {
+            return Err("coordinates must be x,y,z triples".to_string());
+        }
+
+        Ok(pts
+            .flat_map(|p| geom::Point3::from(self.render_to_world(p[0], p[1], p[2])))
+            .collect())
+    }
+
+    /// Transforms a flat list of _world_ coordinates (x,y,z triples) into _render_ coordinates.
+    pub fn world_to_render_flat(&self, xyzs: &[f64]) -> Result<Vec<f64>, String> {
+        let pts = xyzs.chunks_exact(3);
+        if !pts.remainder().is_empty() {
+            return Err("coordinates must be x,y,z triples".to_string());
+        }
+
+        Ok(pts
+            .flat_map(|p| self.to_render([p[0], p[1], p[2]]))
+            .collect())
+    }
+
     /// Creates an extents with the origin at _x,y,z_ and size 0.
     pub fn from_pt(x: f64, y: f64, z: f64) -> Self {
         Self {
@@ -157,6 +222,17 @@ impl Extents3 {
     }
 }
 
+impl Extents3 {
+    /// [`Self::world_to_render`] for a [`geom::Point3`].
+    pub(crate) fn to_render(self, p: geom::Point3) -> geom::Point3 {
+        let [x, y, z] = p
+            .sub(geom::Point3::from(self.origin))
+            .scale(self.max_dim().recip());
+        // note the swap to Y-up
+        [x, z, y]
+    }
+}
+
 impl From<Extents3> for geom::Extents3 {
     fn from(value: Extents3) -> Self {
         geom::Extents3 {
@@ -486,6 +562,10 @@ impl<'a> StoreDecoder<'a> {
         self.decode(f64::from_be_bytes)
     }
 
+    fn u16(&mut self) -> Result<u16, String> {
+        self.decode(u16::from_be_bytes)
+    }
+
     fn u32(&mut self) -> Result<u32, String> {
         self.decode(u32::from_be_bytes)
     }
@@ -509,7 +589,9 @@ impl TileHash {
     }
 
     /// Samples the mesh within a tile at the given spacing.
-    pub fn sample(&self, tile_idx: u32) -> Option<Vec<f32>> {
+    ///
+    /// The sample includes the tile's height range and valid node count, for a [`TileIndex`].
+    pub fn sample(&self, tile_idx: u32) -> Option<TileSample> {
         init_panic_hook();
 
         let tris = self.tiles.get(&tile_idx).filter(|x| !x.is_empty())?;
@@ -539,11 +621,12 @@ impl TileHash {
 
         let scaler = self.extents.max_dim();
         let z = self.extents.origin.z;
-        grid.into_zs()
+        let zs = grid
+            .into_zs()
             .into_iter()
             .map(|x| x.map(|x| ((x - z) / scaler) as f32).unwrap_or(f32::NAN))
-            .collect::<Vec<_>>()
-            .into()
+            .collect::<Vec<_>>();
+        TileSample::from_zs(zs).ok()
     }
 }
 
@@ -666,6 +749,34 @@ impl TileId {
         }
     }
 
+    /// The tile at `lvl` which contains the world XY point, if it is within the extents.
+    fn at(world: &Extents3, p: Point2, lvl: u8) -> Option<Self> {
+        let [dx, dy] = extent_dims(world);
+        let [x, y] = p
+            .sub(Extents2::from(*world).origin)
+            .scale(tile_size(0).recip());
+        let (rx, ry) = (x.floor(), y.floor());
+        if rx < 0.0 || ry < 0.0 || rx >= dx as f64 || ry >= dy as f64 {
+            return None;
+        }
+
+        // the leaf within the root
+        let n = (1u16 << MAX_DEPTH) as f64;
+        let leaf = [x - rx, y - ry].map(|x| (x * n).floor().min(n - 1.0) as u8);
+        let mut t = TileId::from_leaf_index(leaf[0], leaf[1]);
+        t.root = ry as u16 * dx + rx as u16;
+        Some(t.ancestor(lvl))
+    }
+
+    /// Walk up the tree to the tile at `lvl` (returns self if already at or above `lvl`).
+    fn ancestor(&self, lvl: u8) -> Self {
+        let mut t = *self;
+        while t.lod_lvl() > lvl {
+            t = t.parent().expect("not a root");
+        }
+        t
+    }
+
     fn children(&self) -> [Self; 4] {
         let d = self.lod_lvl() as u16;
         let d_ = (d + 1) << 13;
@@ -707,6 +818,30 @@ pub struct ViewableTiles {
     extents: Extents3,
     in_view: Vec<u32>,
     out_view: Vec<u32>,
+    pixel_error: f64,
+    max_tiles: usize,
+    hysteresis: f64,
+    added: Vec<u32>,
+    removed: Vec<u32>,
+    kept: Vec<u32>,
+    /// object -> tiles with data
+    objects: HashMap<String, TileIndex>,
+    occupancy: Option<Occupancy>,
+    prefetch: Vec<u32>,
+    prefetch_frames: u32,
+    velocity: Option<geom::Point3>,
+    /// the recent view positions (render space), oldest first
+    recent: std::collections::VecDeque<geom::Point3>,
+}
+
+/// The number of recent views to estimate the velocity from.
+const RECENT_VIEWS: usize = 4;
+
+/// The tiles with data over all objects, and the world z range of their subtrees (which
+/// includes all their ancestors).
+struct Occupancy {
+    tiles: HashSet<u32>,
+    bounds: HashMap<u32, [f64; 2]>,
 }
 
 #[wasm_bindgen]
@@ -716,6 +851,18 @@ impl ViewableTiles {
             extents: *extents,
             in_view: Vec::new(),
             out_view: Vec::new(),
+            pixel_error: 4.0,
+            max_tiles: usize::MAX,
+            hysteresis: 0.1,
+            added: Vec::new(),
+            removed: Vec::new(),
+            kept: Vec::new(),
+            objects: HashMap::default(),
+            occupancy: None,
+            prefetch: Vec::new(),
+            prefetch_frames: 8,
+            velocity: None,
+            recent: Default::default(),
         }
     }
 
@@ -729,7 +876,205 @@ impl ViewableTiles {
         let area = viewbox.render_area * scaler * scaler;
         // console::debug_1(&format!("area {area:.0} | ha {:.0}", area / 10_000.0).into());
         let lod_res = (area / 10_000.0).powf(0.5) / 2.0;
-        let lod_depth = choose_lod_depth(lod_res) as u8;
+
+        self.traverse(viewbox, None, |t, _, k, _| {
+            t.lod_lvl() < choose_lod_depth(lod_res * k) as u8
+        });
+    }
+
+    /// Calculate the tiles in view, choosing the LOD of each tile by its _screen space error_.
+    ///
+    /// Each tile is refined until its sample spacing, projected from its distance to the
+    /// `camera`, is within the pixel error budget (see [`Self::set_pixel_error`]).
+    /// This gives finer tiles near the camera and coarser tiles towards the horizon.
+    /// The distance is to the tile's box, which is bounded by the z range of its data once
+    /// indexed (see [`Self::set_object_index`]).
+    pub fn update_with_camera(&mut self, viewbox: &Viewbox, camera: &Camera) {
+        let world = self.extents;
+        let scaler = world.max_dim();
+        let budget = self.pixel_error;
+
+        self.traverse(viewbox, Some(camera), |t, (min, max), k, camera| {
+            let Some(camera) = camera else { return false; };
+            let dist = camera.distance_to_box(min, max);
+            camera.screen_size(t.lod_res() / scaler, dist) >= budget * k
+        });
+    }
+
+    /// The maximum on screen size (in pixels) of a tile's sample spacing before it is refined.
+    ///
+    /// Defaults to 4 pixels, smaller values give finer LODs.
+    pub fn set_pixel_error(&mut self, pixels: f64) {
+        self.pixel_error = pixels.max(f64::EPSILON);
+    }
+
+    /// Limit the number of in view tiles, coarser tiles are returned to stay within it.
+    ///
+    /// Each tile holds `128²` 32-bit heights (64 KiB) per object, so a memory budget can be
+    /// turned into a tile count. The roots are always returned, even if they exceed the budget.
+    pub fn set_tile_budget(&mut self, max_tiles: u32) {
+        self.max_tiles = max_tiles as usize;
+    }
+
+    /// Set the tiles which contain data for an object (eg from [`TileHash::tiles`]).
+    ///
+    /// Once any object is set, only tiles with data (for some object) are returned. Empty
+    /// subtrees are skipped, and a tile without data of its own is refined down to the tiles
+    /// that have it. The tiles are expected to cover every level the data is at (as
+    /// [`TileHash::tiles`] does), so a child without data is empty; a tile with data but no
+    /// children with data is kept rather than refined.
+    pub fn set_object_tiles(&mut self, key: &str, tiles: &[u32]) {
+        self.objects
+            .insert(key.to_string(), TileIndex::from_tiles(tiles));
+        self.update_occupancy();
+    }
+
+    /// Set the tiles of an object from its [`TileIndex`].
+    ///
+    /// As [`Self::set_object_tiles`], but the tiles' height ranges also tighten their boxes
+    /// for frustum culling.
+    pub fn set_object_index(&mut self, key: &str, index: &TileIndex) {
+        self.objects.insert(key.to_string(), index.clone());
+        self.update_occupancy();
+    }
+
+    pub fn remove_object(&mut self, key: &str) {
+        self.objects.remove(key);
+        self.update_occupancy();
+    }
+
+    /// The number of view changes (frames) ahead to prefetch tiles for, 0 disables prefetching.
+    ///
+    /// Defaults to 8.
+    pub fn set_prefetch_frames(&mut self, frames: u32) {
+        self.prefetch_frames = frames;
+    }
+
+    /// Set the camera velocity, in **render space** units per view change, for prefetching.
+    ///
+    /// Without a velocity, it is estimated from the last few view boxes. Updates where the view
+    /// has not moved are not counted.
+    pub fn set_velocity(&mut self, x: f64, y: f64, z: f64) {
+        self.velocity = [x, y, z].iter().all(|x| x.is_finite()).then_some([x, y, z]);
+    }
+
+    /// Go back to estimating the velocity from the last few view boxes.
+    pub fn clear_velocity(&mut self) {
+        self.velocity = None;
+    }
+
+    /// The fraction either side of the LOD threshold a tile must cross before it changes LOD.
+    ///
+    /// A refined tile is only coarsened once its error drops below `1 - band` of the threshold,
+    /// and a tile is only refined once its error exceeds `1 + band`. This stops small camera
+    /// movements flipping tiles back and forth between LODs. Defaults to 0.1.
+    pub fn set_hysteresis(&mut self, band: f64) {
+        self.hysteresis = band.clamp(0.0, 0.9);
+    }
+
+    /// The tiles (in and out of view) which are new since the previous update.
+    ///
+    /// In view tiles come first, in priority order.
+    pub fn added(&self) -> Vec<u32> {
+        self.added.clone()
+    }
+
+    /// The tiles from the previous update which are no longer wanted (sorted).
+    pub fn removed(&self) -> Vec<u32> {
+        self.removed.clone()
+    }
+
+    /// The tiles which are wanted in both this and the previous update.
+    pub fn kept(&self) -> Vec<u32> {
+        self.kept.clone()
+    }
+
+    /// The in view tiles, in priority order.
+    ///
+    /// Tiles closest to the centre of the screen come first, where tiles within the same
+    /// finest tile width of the centre distance come nearest to the camera first (when updated
+    /// with one).
+    pub fn in_view_tiles(&self) -> Vec<u32> {
+        self.in_view.clone()
+    }
+
+    pub fn out_view_tiles(&self) -> Vec<u32> {
+        self.out_view.clone()
+    }
+
+    /// The tiles predicted to come into view, from the camera's velocity, in priority order.
+    ///
+    /// These are the tiles which would be in view after the prefetch frames (see
+    /// [`Self::set_prefetch_frames`]) that are not already in or out of view. They should be
+    /// loaded at a lower priority than the in view tiles, to warm the cache.
+    pub fn prefetch_tiles(&self) -> Vec<u32> {
+        self.prefetch.clone()
+    }
+
+    /// The LOD levels of the tiles neighbouring `tile_idx` in the current view.
+    ///
+    /// Ordered west (-x), east (+x), south (-y), north (+y), with `u8::MAX` where there is no
+    /// neighbouring tile. This feeds [`VertexData::fill_vertex_data_from_tile_zs_seamed`].
+    pub fn neighbour_lods(&self, tile_idx: u32) -> Vec<u8> {
+        let world = &self.extents;
+        let xs = TileId::from_num(tile_idx).extents(world);
+
+        // probe just past the middle of each edge
+        let [cx, cy] = xs.origin.add(xs.size.scale(0.5));
+        let [dx, dy] = xs
+            .size
+            .scale(0.5)
+            .add(Point2::all(LODS[MAX_DEPTH as usize] * 0.5));
+        let probes = [[cx - dx, cy], [cx + dx, cy], [cx, cy - dy], [cx, cy + dy]];
+
+        probes
+            .map(|p| {
+                self.in_view
+                    .iter()
+                    .chain(&self.out_view)
+                    .map(|&t| TileId::from_num(t))
+                    .find(|t| contains_pt(&t.extents(world), p))
+                    .map(|t| t.lod_lvl())
+                    .unwrap_or(u8::MAX)
+            })
+            .to_vec()
+    }
+}
+
+impl ViewableTiles {
+    fn update_occupancy(&mut self) {
+        if self.objects.is_empty() {
+            self.occupancy = None;
+            return;
+        }
+
+        let mut tiles = HashSet::default();
+        let mut bounds = HashMap::default();
+        for index in self.objects.values() {
+            tiles.extend(index.tiles());
+            index.subtree_ranges(&self.extents, &mut bounds);
+        }
+
+        self.occupancy = Some(Occupancy { tiles, bounds });
+    }
+
+    /// Walk the tile tree over the view box, refining the in view tiles while `refine` is true.
+    ///
+    /// `refine` is given the tile's box (render space, bounded by the z range of its data when
+    /// known, as used for culling), and the factor to scale its LOD threshold by, which applies
+    /// the hysteresis band. Tiles are refined in priority order (see [`Self::in_view_tiles`]) while the
+    /// refinement fits within the tile budget, otherwise the coarser tile is kept.
+    ///
+    /// The same walk is made over the view moved ahead by the camera's velocity, to find the
+    /// tiles to prefetch. `refine` is given the camera of the (moved) view.
+    fn traverse<F>(&mut self, viewbox: &Viewbox, camera: Option<&Camera>, refine: F)
+    where
+        F: Fn(TileId, (geom::Point3, geom::Point3), f64, Option<&Camera>) -> bool,
+    {
+        use std::collections::BinaryHeap;
+
+        let world = self.extents;
+        let scaler = world.max_dim();
 
         let extents = Extents2::from_iter(
             viewbox
@@ -739,37 +1084,280 @@ impl ViewableTiles {
                 .map(|p| p.scale(scaler).add(world.origin.into())),
         );
 
-        let mut stack = TileId::roots(world);
-        self.in_view.clear();
-        self.out_view.clear();
-
-        while let Some(t) = stack.pop() {
-            let ints = t.extents(world).intersects(extents);
-            let at_depth = t.lod_lvl() == lod_depth;
+        // the view position, to estimate the velocity from
+        let e = extents
+            .intersection(Extents2::from(world))
+            .unwrap_or(extents);
+        let [cx, cy] = e
+            .origin
+            .add(e.size.scale(0.5))
+            .sub(world.origin.into())
+            .scale(scaler.recip());
+        let pos = camera.map(|c| c.position).unwrap_or([cx, 0.0, cy]);
+        // only views which moved, so updates of the same view (eg on loading data) do not
+        // slow the estimate
+        let moved = self.recent.back().is_none_or(|p| p.sub(pos).mag() > 1e-9);
+        if moved {
+            if self.recent.len() == RECENT_VIEWS {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(pos);
+        }
+        let velocity = self.velocity.unwrap_or_else(|| match self.recent.len() {
+            0 | 1 => geom::Point3::zero(),
+            n => self.recent[n - 1]
+                .sub(self.recent[0])
+                .scale(((n - 1) as f64).recip()),
+        });
+
+        // with objects given, prune the empty subtrees
+        let occ = self.occupancy.take();
+        let has_data = |t: &TileId| occ.as_ref().is_none_or(|o| o.tiles.contains(&t.as_num()));
+        // has data itself or below
+        let occupied = |t: &TileId| {
+            occ.as_ref()
+                .is_none_or(|o| o.bounds.contains_key(&t.as_num()))
+        };
+        // the tile's box, using the z range of its subtree's data
+        let tile_box = |t: TileId| {
+            let z = occ
+                .as_ref()
+                .and_then(|o| o.bounds.get(&t.as_num()).copied());
+            render_box(&world, t, z)
+        };
 
-            if ints && at_depth {
-                // simply push this tile into view
-                self.in_view.push(t.as_num());
-            } else if ints {
-                // push the child nodes onto the stack
-                stack.extend(t.children());
+        // tiles refined in the previous update, which stay refined within the band
+        let mut refined = HashSet::default();
+        for t in &self.in_view {
+            let mut t = TileId::from_num(*t).parent();
+            while let Some(x) = t.filter(|x| refined.insert(x.as_num())) {
+                t = x.parent();
+            }
+        }
+        let band = self.hysteresis;
+        let refine = |t: TileId, camera: Option<&Camera>| {
+            let k = if refined.contains(&t.as_num()) {
+                1.0 - band
             } else {
-                // does not intersect, we add as an out of view
+                1.0 + band
+            };
+            !t.is_max_() && refine(t, tile_box(t), k, camera)
+        };
+
+        // walk the tiles of the view moved by `shift` (render space), returning the in view
+        // tiles in priority order and the out of view tiles
+        let walk = |shift: geom::Point3| {
+            let d = [shift[0], shift[2]].scale(scaler);
+            // clamp to the world, which also bounds views towards the horizon
+            let extents = Extents2 {
+                origin: extents.origin.add(d),
+                ..extents
+            }
+            .intersection(Extents2::from(world));
+            let e = extents.unwrap_or(Extents2::from(world));
+            let centre = e.origin.add(e.size.scale(0.5));
+            let frustum = viewbox.frustum.map(|f| f.moved(shift));
+            let camera = camera.map(|c| Camera {
+                position: c.position.add(shift),
+                ..*c
+            });
+
+            let in_view = |t: &TileId| {
+                extents.is_some_and(|e| t.extents(&world).intersects(e))
+                    && frustum.as_ref().is_none_or(|f| {
+                        let (min, max) = tile_box(*t);
+                        f.intersects_box(min, max)
+                    })
+            };
+
+            // closest to the screen centre first, then to the camera
+            let priority = |t: TileId| {
+                let xs = t.extents(&world);
+                let d = xs.origin.add(xs.size.scale(0.5)).sub(centre).mag();
+                let cam = camera
+                    .map(|c| {
+                        let (min, max) = tile_box(t);
+                        c.distance_to_box(min, max)
+                    })
+                    .unwrap_or_default();
+                Prioritised::new(d, cam, t)
+            };
+
+            let mut open = BinaryHeap::new();
+            let mut outs = Vec::new();
+            for t in TileId::roots(&world).into_iter().filter(&occupied) {
+                if in_view(&t) {
+                    open.push(priority(t));
+                } else {
+                    outs.push(t);
+                }
+            }
+
+            // the count of in view tiles, both open and done
+            let mut count = open.len();
+            let mut done = Vec::new();
+            while let Some(p) = open.pop() {
+                let t = p.3;
+                // a tile without data has to be refined to reach its data
+                let missing = !has_data(&t);
+                if !missing && !refine(t, camera.as_ref()) {
+                    done.push(p);
+                    continue;
+                }
+
+                let children = t.children().into_iter().filter(&occupied);
+                let children = children.collect::<Vec<_>>();
+                if !missing && children.is_empty() {
+                    // the data does not go any deeper
+                    done.push(p);
+                    continue;
+                }
+
+                let (ints, outside): (Vec<_>, Vec<_>) = children.into_iter().partition(&in_view);
+                if !missing && count - 1 + ints.len() > self.max_tiles {
+                    // over budget, keep the coarser tile
+                    done.push(p);
+                    continue;
+                }
+
+                count = (count + ints.len()).saturating_sub(1);
+                open.extend(ints.into_iter().map(&priority));
                 // note that this would be at the lowest LOD without overlap of inview
-                self.out_view.push(t.as_num());
+                outs.extend(outside);
             }
-        }
+
+            done.sort_unstable_by(|a, b| b.cmp(a));
+            let ins = done.into_iter().map(|p| p.3).collect::<Vec<_>>();
+
+            // out of view tiles without data are swapped for their shallowest tiles with data
+            let mut outs_ = Vec::new();
+            while let Some(t) = outs.pop() {
+                if has_data(&t) {
+                    outs_.push(t);
+                } else {
+                    outs.extend(t.children().into_iter().filter(&occupied));
+                }
+            }
+            (ins, outs_)
+        };
+
+        let (ins, outs) = walk(geom::Point3::zero());
+        let mut seen = HashSet::default();
+        let in_view = ins
+            .into_iter()
+            .filter(|t| seen.insert(*t))
+            .map(|t| t.as_num());
+        let in_view = in_view.collect::<Vec<_>>();
+        let out_view = outs
+            .into_iter()
+            .filter(|t| seen.insert(*t))
+            .map(|t| t.as_num());
+        let out_view = out_view.collect::<Vec<_>>();
+
+        let ahead = velocity.scale(self.prefetch_frames as f64);
+        let prefetch = if ahead.mag() > 0.0 {
+            let (ins, _) = walk(ahead);
+            ins.into_iter()
+                .filter(|t| seen.insert(*t))
+                .map(|t| t.as_num())
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let prev = self
+            .in_view
+            .drain(..)
+            .chain(self.out_view.drain(..))
+            .collect::<HashSet<_>>();
+        self.in_view = in_view;
+        self.out_view = out_view;
+        self.prefetch = prefetch;
+        self.occupancy = occ;
+
+        // the change sets
+        let (kept, added) = self
+            .in_view
+            .iter()
+            .chain(&self.out_view)
+            .partition(|t| prev.contains(t));
+        self.kept = kept;
+        self.added = added;
+        let wanted = self.kept.iter().collect::<HashSet<_>>();
+        self.removed = prev
+            .iter()
+            .filter(|t| !wanted.contains(t))
+            .copied()
+            .collect();
+        self.removed.sort_unstable();
     }
+}
 
-    pub fn in_view_tiles(&self) -> Vec<u32> {
-        self.in_view.clone()
+/// The tile's box in render space (Y-up), over the full world z range.
+fn render_box(
+    world: &Extents3,
+    t: TileId,
+    z_range: Option<[f64; 2]>,
+) -> (geom::Point3, geom::Point3) {
+    let scaler = world.max_dim();
+    let xs = t.extents(world);
+    let min = xs
+        .origin
+        .sub(Extents2::from(*world).origin)
+        .scale(scaler.recip());
+    let max = min.add(xs.size.scale(scaler.recip()));
+    let [min_y, max_y] = z_range
+        .map(|z| z.map(|z| (z - world.origin.z) / scaler))
+        .unwrap_or([0.0, world.size.z / scaler]);
+    ([min[0], min_y, min[1]], [max[0], max_y, max[1]])
+}
+
+/// A tile ordered by priority, where _greater_ is more important.
+///
+/// Tiles are ordered by their ring of distance from the screen centre (each ring the width of
+/// the finest tile), then camera distance, then the exact centre distance.
+struct Prioritised(f64, f64, f64, TileId);
+
+impl Prioritised {
+    fn new(centre_dist: f64, camera_dist: f64, t: TileId) -> Self {
+        let ring = (centre_dist / tile_size(MAX_DEPTH as usize)).floor();
+        Self(ring, camera_dist, centre_dist, t)
     }
+}
 
-    pub fn out_view_tiles(&self) -> Vec<u32> {
-        self.out_view.clone()
+impl PartialEq for Prioritised {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Prioritised {}
+
+impl PartialOrd for Prioritised {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Prioritised {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // smaller values are more important
+        other
+            .0
+            .total_cmp(&self.0)
+            .then(other.1.total_cmp(&self.1))
+            .then(other.2.total_cmp(&self.2))
+            .then(other.3.cmp(&self.3))
     }
 }
 
+/// Half-open containment test (includes the min edges, excludes the max edges).
+fn contains_pt(extents: &Extents2, [x, y]: Point2) -> bool {
+    let [x0, y0] = extents.origin;
+    let [x1, y1] = extents.max();
+    x >= x0 && x < x1 && y >= y0 && y < y1
+}
+
 fn choose_lod_depth(resolution: f64) -> usize {
     LODS.into_iter()
         .enumerate()
@@ -783,10 +1371,30 @@ pub struct VertexData {
     positions: Vec<f32>,
     indices: Vec<u32>,
     normals: Vec<f32>,
+    colors: Vec<f32>,
+    uvs: Vec<f32>,
+    facing: Vec<f32>,
 }
 
 type VertexDataPts = Vec<Option<(u32, geom::Point3)>>;
 
+/// The smooth triangulation of a tile's `zs` (render space) as **world** space triangles.
+fn tile_tris(extents: &Extents3, tile: TileId, zs: Vec<f32>) -> Vec<Tri> {
+    let pts = VertexData::build_points(extents, tile, zs);
+    let mut vd = VertexData::default();
+    vd.add_indices_smooth(&pts);
+
+    let pts = pts
+        .into_iter()
+        .flatten()
+        .map(|(_, [x, y, z])| extents.render_to_world(x, y, z).into())
+        .collect::<Vec<geom::Point3>>();
+    vd.indices
+        .chunks_exact(3)
+        .map(|f| [f[0], f[1], f[2]].map(|i| pts[i as usize]))
+        .collect()
+}
+
 #[wasm_bindgen]
 impl VertexData {
     /// Fills the component buffers of a babylonjs VertexData with a tile's meshes.
@@ -819,9 +1427,151 @@ impl VertexData {
                 .collect(),
             indices: Vec::with_capacity(max_tri_len * 3),
             normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            facing: Vec::new(),
+        };
+
+        this.add_indices_smooth(&pts);
+        this.add_normals_smooth();
+
+        this
+    }
+
+    /// Fills the vertex data as [`Self::fill_vertex_data_from_tile_zs_smooth`] does, but treats
+    /// the tile edges to hide cracks against neighbouring tiles of a different LOD.
+    ///
+    /// `neighbour_lods` are the LOD levels of the neighbouring tiles, ordered
+    /// **west (-x), east (+x), south (-y), north (+y)**.
+    /// A missing entry, or a level greater than the maximum depth, is taken as _no neighbour_
+    /// and that edge is left alone (see [`ViewableTiles::neighbour_lods`]).
+    ///
+    /// > _Consumes `zs` in the process, so it will not be available in JS afterwards._
+    pub fn fill_vertex_data_from_tile_zs_seamed(
+        extents: &Extents3,
+        tile_idx: u32,
+        mut zs: Vec<f32>,
+        seam: EdgeSeam,
+        neighbour_lods: &[u8],
+    ) -> Self {
+        init_panic_hook();
+
+        let tile = TileId::from_num(tile_idx);
+        let lvl = tile.lod_lvl();
+        let nbrs: [Option<u8>; 4] = std::array::from_fn(|i| {
+            neighbour_lods
+                .get(i)
+                .copied()
+                .filter(|&x| x <= MAX_DEPTH && x != lvl)
+        });
+
+        if seam == EdgeSeam::Stitch {
+            stitch_edges(tile, &mut zs, nbrs);
+        }
+
+        let pts = Self::build_points(extents, tile, zs);
+
+        let mut this = Self {
+            positions: pts
+                .iter()
+                .filter_map(|x| *x)
+                .flat_map(|x| x.1.map(|x| x as f32))
+                .collect(),
+            indices: Vec::with_capacity(COUNT.pow(2) * 2 * 3),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            facing: Vec::new(),
+        };
+
+        this.add_indices_smooth(&pts);
+        this.add_normals_smooth();
+
+        if seam == EdgeSeam::Skirt {
+            // hang the skirt as deep as the coarsest neighbour's resolution
+            let depth = nbrs
+                .into_iter()
+                .flatten()
+                .chain([lvl])
+                .min()
+                .map(|l| LODS[l as usize] / extents.max_dim())
+                .unwrap_or_default();
+            this.add_skirts(&pts, nbrs.map(|x| x.is_some()), depth as f32);
+        }
+
+        this
+    }
+
+    /// Fills the component buffers of a babylonjs VertexData with a tile's meshes, _flat shaded_.
+    ///
+    /// This shares the semantics of [`Self::fill_vertex_data_from_tile_zs_smooth`], but the
+    /// positions are de-indexed (3 per face) and each face gets its own normal, so the
+    /// triangulation is visible.
+    /// The indices are still filled (sequentially), so it can be used as `isUnIndexed`.
+    ///
+    /// > _Consumes `zs` in the process, so it will not be available in JS afterwards._
+    pub fn fill_vertex_data_from_tile_zs_flat(
+        extents: &Extents3,
+        tile_idx: u32,
+        zs: Vec<f32>,
+    ) -> Self {
+        init_panic_hook();
+
+        let tile = TileId::from_num(tile_idx);
+
+        let pts = Self::build_points(extents, tile, zs);
+
+        let mut this = Self {
+            positions: pts
+                .iter()
+                .filter_map(|x| *x)
+                .flat_map(|x| x.1.map(|x| x as f32))
+                .collect(),
+            indices: Vec::with_capacity(COUNT.pow(2) * 2 * 3),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            facing: Vec::new(),
         };
 
         this.add_indices_smooth(&pts);
+        this.flatten();
+
+        this
+    }
+
+    /// Fills the vertex data with an adaptive triangulation (RTIN) of a tile's meshes.
+    ///
+    /// This shares the semantics of [`Self::fill_vertex_data_from_tile_zs_smooth`], but
+    /// only refines the mesh where the vertical error would exceed `max_error`, which is in
+    /// **world** units (metres).
+    /// Flat ground gets far fewer triangles, and only the vertices in use are emitted.
+    ///
+    /// > _Consumes `zs` in the process, so it will not be available in JS afterwards._
+    pub fn fill_vertex_data_from_tile_zs_rtin(
+        extents: &Extents3,
+        tile_idx: u32,
+        zs: Vec<f32>,
+        max_error: f64,
+    ) -> Self {
+        init_panic_hook();
+
+        let tile = TileId::from_num(tile_idx);
+        let tris = rtin::triangulate(&zs, (max_error / extents.max_dim()) as f32);
+        let pts = Self::build_points(extents, tile, zs);
+
+        // remap the grid indices to only the vertices in use
+        let mut map = vec![None; pts.len()];
+        let mut this = Self::default();
+        for i in tris.into_iter().flatten() {
+            let idx = *map[i].get_or_insert_with(|| {
+                let (_, p) = pts[i].expect("RTIN only emits valid points");
+                this.positions.extend(p.map(|x| x as f32));
+                (this.positions.len() / 3 - 1) as u32
+            });
+            this.indices.push(idx);
+        }
+
         this.add_normals_smooth();
 
         this
@@ -870,6 +1620,8 @@ impl VertexData {
         let size = stride - 1;
 
         // we consider each grid cell by its lower left hand point
+        // NOTE: every case winds anti-clockwise (looking down), so the normals point up and
+        // the underside can be distinguished
         for y in 0..size {
             for x in 0..size {
                 let bl = y * stride + x;
@@ -933,68 +1685,364 @@ impl VertexData {
             .collect();
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.positions.is_empty() || self.normals.is_empty()
-    }
-
-    pub fn positions(&self) -> Vec<f32> {
-        self.positions.clone()
-    }
+    /// De-indexes the positions so each face has its own vertices, and sets per-face normals.
+    ///
+    /// Requires that positions and indices are set.
+    fn flatten(&mut self) {
+        let mut positions = Vec::with_capacity(self.indices.len() * 3);
+        let mut normals = Vec::with_capacity(self.indices.len() * 3);
 
-    pub fn indices(&self) -> Vec<u32> {
-        self.indices.clone()
-    }
+        for face in self.indices.chunks_exact(3) {
+            let face: [u32; 3] = face.try_into().unwrap();
+            let [pa, pb, pc] = face.map(|x| {
+                let x = x as usize;
+                let p: [f32; 3] = self.positions[x * 3..x * 3 + 3].try_into().unwrap();
+                p.map(|x| x as f64)
+            });
+
+            let n = xprod(pc.sub(pa), pb.sub(pa)).unit().map(|x| x as f32);
+            for p in [pa, pb, pc] {
+                positions.extend(p.map(|x| x as f32));
+                normals.extend(n);
+            }
+        }
+
+        self.indices = (0..positions.len() as u32 / 3).collect();
+        self.positions = positions;
+        self.normals = normals;
+    }
+
+    /// Hangs vertical skirts of `depth` (render space) from the flagged tile edges.
+    ///
+    /// Requires that positions, indices, and normals are set. The skirt vertices take the normal
+    /// of the vertex they hang from so the shading does not change across the seam.
+    fn add_skirts(&mut self, pts: &VertexDataPts, sides: [bool; 4], depth: f32) {
+        for (side, _) in sides.into_iter().enumerate().filter(|x| x.1) {
+            // walk the edge anti-clockwise (looking down) so the skirt faces outwards
+            let mut edge = edge_indices(side);
+            if side == 0 || side == 3 {
+                edge.reverse();
+            }
+
+            let mut prev: Option<(u32, u32)> = None;
+            for i in edge {
+                let Some((idx, _)) = pts[i] else {
+                    prev = None;
+                    continue;
+                };
+
+                let idx_ = (self.positions.len() / 3) as u32;
+                let (p, n) = (idx as usize * 3, idx as usize * 3 + 3);
+                let [x, y, z]: [f32; 3] = self.positions[p..n].try_into().unwrap();
+                let normal: [f32; 3] = self.normals[p..n].try_into().unwrap();
+                self.positions.extend([x, y - depth, z]);
+                self.normals.extend(normal);
+
+                if let Some((a, a_)) = prev {
+                    self.indices.extend([a, idx_, idx]);
+                    self.indices.extend([a, a_, idx_]);
+                }
+
+                prev = Some((idx, idx_));
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty() || self.normals.is_empty()
+    }
+
+    pub fn positions(&self) -> Vec<f32> {
+        self.positions.clone()
+    }
+
+    pub fn indices(&self) -> Vec<u32> {
+        self.indices.clone()
+    }
 
     pub fn normals(&self) -> Vec<f32> {
         self.normals.clone()
     }
+
+    /// The RGBA vertex colours, empty unless a colour ramp has been applied.
+    pub fn colors(&self) -> Vec<f32> {
+        self.colors.clone()
+    }
+
+    /// Per-vertex facing, `1` for the top side and `-1` for the underside.
+    ///
+    /// Empty unless [`Self::make_double_sided`] has been called.
+    pub fn facing(&self) -> Vec<f32> {
+        self.facing.clone()
+    }
+
+    /// Makes the geometry double sided so the top and underside can be told apart.
+    ///
+    /// The vertices are duplicated for the underside with flipped normals and reversed winding,
+    /// and the [`Self::facing`] attribute is filled.
+    /// This works with back-face culling on, and any colours are duplicated so a material can
+    /// tint the underside using the facing.
+    pub fn make_double_sided(&mut self) {
+        if !self.facing.is_empty() {
+            return; // already double sided
+        }
+
+        let n = (self.positions.len() / 3) as u32;
+
+        self.positions.extend_from_within(..);
+        self.colors.extend_from_within(..);
+        self.uvs.extend_from_within(..);
+        self.normals
+            .extend(self.normals.clone().into_iter().map(|x| -x));
+
+        let under = self
+            .indices
+            .chunks_exact(3)
+            .flat_map(|f| [f[0] + n, f[2] + n, f[1] + n])
+            .collect::<Vec<_>>();
+        self.indices.extend(under);
+
+        self.facing = vec![1.0; n as usize];
+        self.facing.resize(n as usize * 2, -1.0);
+    }
+
+    /// The texture coordinates (2 per vertex), empty unless an image has been draped.
+    pub fn uvs(&self) -> Vec<f32> {
+        self.uvs.clone()
+    }
+
+    /// Sets the texture coordinates to drape `image` over the vertices.
+    ///
+    /// The UVs are in world XY relative to the image's extents, so an image cut to a tile (see
+    /// [`GeoImage::tile_window`] and [`GeoImage::crop`]) can be used directly.
+    /// `extents` must be the extents the vertex data was built with.
+    pub fn apply_image_uvs(&mut self, extents: &Extents3, image: &GeoImage) {
+        self.uvs = self
+            .positions
+            .chunks_exact(3)
+            .flat_map(|p| {
+                let [x, y, z] = [p[0], p[1], p[2]].map(|x| x as f64);
+                image.uv(extents.render_to_world(x, y, z).into())
+            })
+            .collect();
+    }
+
+    /// Colours each vertex by its elevation (RL) using `ramp`.
+    ///
+    /// The positions are in render space, so they are mapped back to world RLs with `extents`
+    /// (which must be the extents the vertex data was built with).
+    pub fn apply_colour_ramp(&mut self, extents: &Extents3, ramp: &ColourRamp) {
+        self.colors = self
+            .positions
+            .chunks_exact(3)
+            .flat_map(|p| {
+                let [x, y, z] = [p[0], p[1], p[2]].map(|x| x as f64);
+                ramp.rgba(extents.render_to_world(x, y, z).z)
+            })
+            .collect();
+    }
+}
+
+/// Line buffers for rendering a tile's mesh edges (eg as a wireframe overlay).
+///
+/// The positions are the same as the smooth [`VertexData`] for the tile, and the indices are
+/// pairs of positions, one pair per line.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct EdgeData {
+    positions: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl EdgeData {
+    /// Builds the deduplicated edges of a tile's grid triangles.
+    ///
+    /// If `boundary_only` is set, only the edges which belong to a single triangle are returned,
+    /// this outlines the data extent and any `NaN` holes.
+    /// See [`VertexData::fill_vertex_data_from_tile_zs_smooth`] for the semantics of `zs`.
+    ///
+    /// > _Consumes `zs` in the process, so it will not be available in JS afterwards._
+    pub fn from_tile_zs(
+        extents: &Extents3,
+        tile_idx: u32,
+        zs: Vec<f32>,
+        boundary_only: bool,
+    ) -> Self {
+        init_panic_hook();
+
+        let tile = TileId::from_num(tile_idx);
+        let pts = VertexData::build_points(extents, tile, zs);
+
+        let mut vd = VertexData {
+            positions: pts
+                .iter()
+                .filter_map(|x| *x)
+                .flat_map(|x| x.1.map(|x| x as f32))
+                .collect(),
+            ..Default::default()
+        };
+        vd.add_indices_smooth(&pts);
+
+        // count the faces on each edge
+        let mut edges: HashMap<(u32, u32), u8> = HashMap::default();
+        for face in vd.indices.chunks_exact(3) {
+            for (a, b) in [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])] {
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+
+        let mut edges = edges
+            .into_iter()
+            .filter_map(|(e, n)| (!boundary_only || n == 1).then_some(e))
+            .collect::<Vec<_>>();
+        edges.sort_unstable();
+
+        Self {
+            positions: vd.positions,
+            indices: edges.into_iter().flat_map(|(a, b)| [a, b]).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn positions(&self) -> Vec<f32> {
+        self.positions.clone()
+    }
+
+    pub fn indices(&self) -> Vec<u32> {
+        self.indices.clone()
+    }
+}
+
+/// How the edges of a tile are treated to hide the cracks against neighbouring tiles at a
+/// different LOD.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EdgeSeam {
+    /// Each tile is built on its own.
+    None,
+    /// Vertical skirts are hung from edges which border a different LOD.
+    Skirt,
+    /// Edges bordering a coarser LOD are snapped to the coarser resolution.
+    Stitch,
+}
+
+/// The grid indices along a tile edge, in increasing x/y order.
+///
+/// Sides are ordered west (-x), east (+x), south (-y), north (+y).
+fn edge_indices(side: usize) -> [usize; COUNT] {
+    let last = COUNT - 1;
+    std::array::from_fn(|i| match side {
+        0 => i * COUNT,
+        1 => i * COUNT + last,
+        2 => i,
+        _ => last * COUNT + i,
+    })
+}
+
+/// Snaps the edge zs to the resolution of any coarser neighbours.
+///
+/// The coarse samples always coincide with one of our samples (resolutions differ by powers of
+/// 2 and tiles are offset by whole multiples of `COUNT - 1` samples), so the coarse edge is
+/// reconstructed from our own zs and the samples in between are interpolated onto it.
+/// Past the first and last coarse samples we interpolate towards the tile corner, which is
+/// shared with the other neighbours and so is never moved.
+fn stitch_edges(tile: TileId, zs: &mut [f32], neighbour_lods: [Option<u8>; 4]) {
+    let lvl = tile.lod_lvl();
+    let last = COUNT - 1;
+
+    for (side, nbr) in neighbour_lods.into_iter().enumerate() {
+        let Some(nbr) = nbr.filter(|&x| x < lvl) else { continue; };
+
+        let ratio = 1usize << (lvl - nbr);
+        // the offset (in samples) from the coarse grid origin along the edge
+        let axis = if side < 2 { 1 } else { 0 };
+        let offset = tile.ovec().sub(tile.ancestor(nbr).ovec())[axis] / tile.lod_res();
+        let offset = offset.round() as usize;
+
+        let edge = edge_indices(side);
+        let knots = (0..COUNT)
+            .filter(|i| *i == 0 || *i == last || (i + offset) & (ratio - 1) == 0)
+            .collect::<Vec<_>>();
+
+        for k in knots.windows(2) {
+            let (a, b) = (k[0], k[1]);
+            let (za, zb) = (zs[edge[a]], zs[edge[b]]);
+            if !za.is_finite() || !zb.is_finite() {
+                continue;
+            }
+
+            for i in a + 1..b {
+                let z = &mut zs[edge[i]];
+                if z.is_finite() {
+                    let t = (i - a) as f32 / (b - a) as f32;
+                    *z = za + (zb - za) * t;
+                }
+            }
+        }
+    }
 }
 
 /// The camera's view box in **render space**.
 ///
 /// To build the view box, one can imagine the viewport defines 4 planes parallel to the camera
 /// direction. These intersect with the min/max z world extents.
+/// A perspective view box is instead built from the camera's view frustum, see
+/// [`Viewbox::from_view_projection`].
 #[wasm_bindgen]
 #[derive(Debug)]
 pub struct Viewbox {
     min_ps: [Point2; 4],
     max_ps: [Point2; 4],
     render_area: f64,
+    frustum: Option<Frustum>,
 }
 
 #[wasm_bindgen]
 impl Viewbox {
-    /// Build the viewbox.
+    /// Build the viewbox from the viewport's corners and the camera direction.
     ///
-    /// Unfortunately the interface is wonky to reduce the amount of serialisation that
-    /// must pass through the WASM boundary.
-    /// As such there are two arguments.
-    /// The first one is the **data extents** in **world space**.
-    /// The second is an array of floats representing **points in render space**,
-    /// which will be destructured into:
-    /// - camera direction
-    /// - viewport (bottom-left)
-    /// - viewport (bottom-right)
-    /// - viewport (top-right)
-    /// - viewport (top-left)
-    ///
-    /// That is, we expect an array of length 15 floats.
-    /// If the length is not 15, we panic which will give weird WASM errors or might even
-    /// silently fail.
-    pub fn calculate(extents: &Extents3, data: &[f64]) -> Self {
-        use geom::Point3 as P;
-
-        let camera_dir = P::unit(data[..3].try_into().unwrap());
-        let r1: P = data[3..6].try_into().unwrap();
-        let r2: P = data[6..9].try_into().unwrap();
-        let r3: P = data[9..12].try_into().unwrap();
-        let r4: P = data[12..].try_into().unwrap();
+    /// The **data extents** are in **world space**, the rest are in **render space**.
+    /// The viewport corners are where the camera's rays through the corners of the screen
+    /// start (eg the picking ray origins), and must form a non-degenerate rectangle.
+    pub fn from_viewport(
+        extents: &Extents3,
+        camera_dir: &Point3,
+        bottom_left: &Point3,
+        bottom_right: &Point3,
+        top_right: &Point3,
+        top_left: &Point3,
+    ) -> Result<Viewbox, String> {
+        let dir = geom::Point3::from(*camera_dir);
+        let rs = [bottom_left, bottom_right, top_right, top_left].map(|p| geom::Point3::from(*p));
+        if dir
+            .iter()
+            .chain(rs.iter().flatten())
+            .any(|x| !x.is_finite())
+        {
+            return Err("camera direction and viewport must be finite".to_string());
+        }
+        if dir.mag() < 1e-12 {
+            return Err("camera direction must be non-zero".to_string());
+        }
+        let camera_dir = dir.unit();
+        let [r1, r2, r3, r4] = rs;
 
         // area is magnitude of cross product!
         // assume sides of rectangle are 1->2 and 1->4
         let area = xprod(r2.sub(r1), r4.sub(r1)).mag();
+        if !area.is_normal() {
+            return Err("viewport is degenerate".to_string());
+        }
 
-        let cy = camera_dir[1];
+        // a horizontal camera never reaches the z planes, nudge it to keep the box finite
+        let cy = match camera_dir[1] {
+            y if y.abs() < 1e-6 => -1e-6,
+            y => y,
+        };
         let prj = |z| {
             [r1, r2, r3, r4].map(|r| {
                 let d = (z - r[1]) / cy;
@@ -1007,11 +2055,287 @@ impl Viewbox {
         let min_ps = prj(0.0);
         let max_ps = prj(max_y);
 
-        Self {
+        Ok(Self {
             min_ps,
             max_ps,
             render_area: area,
+            frustum: None,
+        })
+    }
+
+    /// Build the viewbox from a flat array, see [`Self::from_viewport`].
+    ///
+    /// `data` is 15 floats of **points in render space**, which will be destructured into:
+    /// - camera direction
+    /// - viewport (bottom-left)
+    /// - viewport (bottom-right)
+    /// - viewport (top-right)
+    /// - viewport (top-left)
+    pub fn calculate(extents: &Extents3, data: &[f64]) -> Result<Viewbox, String> {
+        if data.len() != 15 {
+            return Err(format!("expecting 15 viewbox values, found {}", data.len()));
+        }
+
+        let p = |i: usize| Point3::new(data[i], data[i + 1], data[i + 2]);
+        Self::from_viewport(extents, &p(0), &p(3), &p(6), &p(9), &p(12))
+    }
+
+    /// Build the viewbox from the camera's view frustum.
+    ///
+    /// `view_projection` is the 16 floats of the **render space** view-projection matrix, with
+    /// points as row vectors (`p * M`, as Babylon's `Matrix.m`) and OpenGL style clip space.
+    /// Tiles are culled by testing their 3D boxes against the frustum planes. The far plane
+    /// is clamped to the furthest corner of the world extents, so views towards the horizon
+    /// stay bounded.
+    pub fn from_view_projection(
+        extents: &Extents3,
+        view_projection: &[f64],
+    ) -> Result<Viewbox, String> {
+        let m: &[f64; 16] = view_projection.try_into().map_err(|_| {
+            format!(
+                "expecting 16 view-projection values, found {}",
+                view_projection.len()
+            )
+        })?;
+        if m.iter().any(|x| !x.is_finite()) {
+            return Err("view-projection matrix must be finite".to_string());
+        }
+
+        Self::from_frustum(extents, Frustum::from_matrix(m)?)
+    }
+
+    /// Build the viewbox for an orthographic camera, such as a plan or section view.
+    ///
+    /// `camera` is 9 floats in **render space**: the camera position, the view direction, then
+    /// the up direction. `bounds` are the 4 ortho bounds (left, right, bottom, top), relative to
+    /// the camera position along its right and up axes. As with
+    /// [`Self::from_view_projection`], the view extends to the far corner of the world.
+    pub fn orthographic(
+        extents: &Extents3,
+        camera: &[f64],
+        bounds: &[f64],
+    ) -> Result<Viewbox, String> {
+        let [px, py, pz, dx, dy, dz, ux, uy, uz]: [f64; 9] = camera
+            .try_into()
+            .map_err(|_| format!("expecting 9 camera values, found {}", camera.len()))?;
+        let [l, r, b, t]: [f64; 4] = bounds
+            .try_into()
+            .map_err(|_| format!("expecting 4 ortho bounds, found {}", bounds.len()))?;
+        if camera.iter().chain(bounds).any(|x| !x.is_finite()) {
+            return Err("camera and ortho bounds must be finite".to_string());
+        }
+        if l >= r || b >= t {
+            return Err(format!(
+                "ortho bounds are degenerate: [{l}, {r}] x [{b}, {t}]"
+            ));
+        }
+
+        let p = [px, py, pz];
+        let dir = [dx, dy, dz];
+        if dir.mag() == 0.0 {
+            return Err("view direction must be non-zero".to_string());
+        }
+        let dir = dir.unit();
+        // left handed, as the render space
+        let right = xprod([ux, uy, uz], dir);
+        if right.mag() < 1e-9 {
+            return Err("up direction must not be parallel to the view direction".to_string());
+        }
+        let right = right.unit();
+        let up = xprod(dir, right);
+
+        let plane = |n: geom::Point3, offset: f64| {
+            let d = n.into_iter().zip(p).map(|(a, b)| a * b).sum::<f64>();
+            [n[0], n[1], n[2], offset - d]
+        };
+        let frustum = Frustum {
+            planes: [
+                plane(right, -l),
+                plane(right.scale(-1.0), r),
+                plane(up, -b),
+                plane(up.scale(-1.0), t),
+                plane(dir, 0.0),
+                // placed by the clamp to the world
+                plane(dir.scale(-1.0), f64::INFINITY),
+            ],
+        };
+
+        Self::from_frustum(extents, frustum)
+    }
+}
+
+impl Viewbox {
+    /// The viewbox of the frustum, with its far plane clamped to the world extents.
+    fn from_frustum(extents: &Extents3, frustum: Frustum) -> Result<Self, String> {
+        let scaler = extents.max_dim();
+        let world_max = [extents.size.x, extents.size.z, extents.size.y].scale(scaler.recip());
+        let frustum = frustum.clamp_far(world_max);
+        let cs = frustum.corners().ok_or("view frustum is degenerate")?;
+
+        let xz = |p: &geom::Point3| [p[0], p[2]];
+        let near = [cs[0], cs[1], cs[2], cs[3]];
+        let far = [cs[4], cs[5], cs[6], cs[7]];
+        // the cross section halfway into the (clamped) frustum
+        let mid = [0, 1, 2, 3].map(|i| near[i].add(far[i].sub(near[i]).scale(0.5)));
+        let area = xprod(mid[1].sub(mid[0]), mid[3].sub(mid[0])).mag();
+
+        Ok(Self {
+            min_ps: near.each_ref().map(xz),
+            max_ps: far.each_ref().map(xz),
+            render_area: area,
+            frustum: Some(frustum),
+        })
+    }
+}
+
+/// A view frustum in **render space**.
+///
+/// The planes are ordered left, right, bottom, top, near, far. Each is `[a, b, c, d]` with a
+/// unit normal pointing inwards, so a point is inside when `a x + b y + c z + d >= 0`.
+#[derive(Copy, Clone, Debug)]
+struct Frustum {
+    planes: [[f64; 4]; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a row vector view-projection matrix (Gribb-Hartmann).
+    fn from_matrix(m: &[f64; 16]) -> Result<Self, String> {
+        let col = |j: usize| [m[j], m[4 + j], m[8 + j], m[12 + j]];
+        let add = |a: [f64; 4], b: [f64; 4], s: f64| [0, 1, 2, 3].map(|i| a[i] + b[i] * s);
+        let (w, x, y, z) = (col(3), col(0), col(1), col(2));
+
+        let mut planes = [
+            add(w, x, 1.0),
+            add(w, x, -1.0),
+            add(w, y, 1.0),
+            add(w, y, -1.0),
+            add(w, z, 1.0),
+            add(w, z, -1.0),
+        ];
+        for p in &mut planes {
+            let len = [p[0], p[1], p[2]].mag();
+            if !len.is_normal() {
+                return Err("view-projection matrix is degenerate".to_string());
+            }
+            *p = p.map(|x| x / len);
         }
+
+        Ok(Self { planes })
+    }
+
+    /// Pulls the far plane in to the furthest corner of the box `[0, max]`.
+    fn clamp_far(mut self, max: geom::Point3) -> Self {
+        let [a, b, c, d] = self.planes[4];
+        let furthest = (0..8)
+            .map(|i| [i & 1, i >> 1 & 1, i >> 2 & 1].map(|x| x as f64))
+            .map(|k| a * k[0] * max[0] + b * k[1] * max[1] + c * k[2] * max[2] + d)
+            .fold(f64::NEG_INFINITY, f64::max)
+            .max(0.0);
+
+        let far = self.corners().map(|cs| {
+            cs[4..]
+                .iter()
+                .map(|p| a * p[0] + b * p[1] + c * p[2] + d)
+                .fold(f64::NEG_INFINITY, f64::max)
+        });
+        if far.is_none_or(|f| !f.is_finite() || f > furthest) {
+            self.planes[5] = [-a, -b, -c, furthest - d];
+        }
+
+        self
+    }
+
+    /// The near corners then the far corners, each ordered bottom-left, bottom-right,
+    /// top-right, top-left.
+    fn corners(&self) -> Option<[geom::Point3; 8]> {
+        let [l, r, b, t, n, f] = self.planes;
+        let mut cs = [geom::Point3::zero(); 8];
+        for (i, z) in [n, f].into_iter().enumerate() {
+            for (j, (x, y)) in [(l, b), (r, b), (r, t), (l, t)].into_iter().enumerate() {
+                cs[i * 4 + j] = intersect_planes(x, y, z)?;
+            }
+        }
+        Some(cs)
+    }
+
+    /// The frustum translated by `d`.
+    fn moved(mut self, d: geom::Point3) -> Self {
+        for [a, b, c, e] in &mut self.planes {
+            *e -= *a * d[0] + *b * d[1] + *c * d[2];
+        }
+        self
+    }
+
+    /// Whether the box intersects the frustum, conservatively (some boxes just outside a
+    /// corner are kept).
+    fn intersects_box(&self, min: geom::Point3, max: geom::Point3) -> bool {
+        self.planes.iter().all(|[a, b, c, d]| {
+            // the box corner furthest along the normal
+            let p = [(a, 0), (b, 1), (c, 2)].map(|(n, i)| if *n >= 0.0 { max[i] } else { min[i] });
+            a * p[0] + b * p[1] + c * p[2] + d >= 0.0
+        })
+    }
+}
+
+/// The point where three planes meet, `None` if any are parallel.
+fn intersect_planes(p1: [f64; 4], p2: [f64; 4], p3: [f64; 4]) -> Option<geom::Point3> {
+    let n = |p: [f64; 4]| [p[0], p[1], p[2]];
+    let (n1, n2, n3) = (n(p1), n(p2), n(p3));
+    let (c23, c31, c12) = (xprod(n2, n3), xprod(n3, n1), xprod(n1, n2));
+    let det = n1.into_iter().zip(c23).map(|(a, b)| a * b).sum::<f64>();
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let p = c23
+        .scale(-p1[3])
+        .add(c31.scale(-p2[3]))
+        .add(c12.scale(-p3[3]))
+        .scale(det.recip());
+    Some(p)
+}
+
+/// A perspective camera in **render space**, for choosing LODs by screen space error.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    position: geom::Point3,
+    /// Pixels per render unit at a distance of one.
+    px_per_unit: f64,
+}
+
+#[wasm_bindgen]
+impl Camera {
+    /// A camera at the render space _x,y,z_ with a vertical field of view `fov` (radians),
+    /// looking into a viewport `viewport_height` pixels high.
+    pub fn new(x: f64, y: f64, z: f64, fov: f64, viewport_height: f64) -> Result<Camera, String> {
+        if !(fov > 0.0 && fov < std::f64::consts::PI) {
+            return Err(format!("field of view must be within (0, π), found {fov}"));
+        }
+        if !viewport_height.is_finite() || viewport_height <= 0.0 {
+            return Err("viewport height must be positive".to_string());
+        }
+        if ![x, y, z].iter().all(|x| x.is_finite()) {
+            return Err("camera position must be finite".to_string());
+        }
+
+        Ok(Self {
+            position: [x, y, z],
+            px_per_unit: viewport_height / (2.0 * (fov * 0.5).tan()),
+        })
+    }
+}
+
+impl Camera {
+    /// The distance from the camera to the nearest point of the box.
+    fn distance_to_box(&self, min: geom::Point3, max: geom::Point3) -> f64 {
+        let p = self.position.max_all(min).min_all(max);
+        p.sub(self.position).mag()
+    }
+
+    /// The projected size (pixels) of a render space `size` at `dist`.
+    fn screen_size(&self, size: f64, dist: f64) -> f64 {
+        size * self.px_per_unit / dist.max(f64::EPSILON)
     }
 }
 
@@ -1019,6 +2343,24 @@ impl Viewbox {
 mod tests {
     use super::*;
 
+    fn world() -> Extents3 {
+        Extents3 {
+            origin: Point3::zero(),
+            size: [4064.0, 4064.0, 100.0].into(),
+        }
+    }
+
+    /// A view box over the whole world, with no frustum.
+    fn whole_world_viewbox(render_area: f64) -> Viewbox {
+        let ps = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        Viewbox {
+            min_ps: ps,
+            max_ps: ps,
+            render_area,
+            frustum: None,
+        }
+    }
+
     #[test]
     fn assert_tris_fn() {
         let mesh = TriangleMeshSurface {
@@ -1190,4 +2532,676 @@ mod tests {
         let x = x.parent();
         assert_eq!(x, None);
     }
+
+    #[test]
+    fn stitch_edges_testing() {
+        // south edge alternates 0/1, stitching to a parent LOD should flatten the odd samples
+        let mut zs = vec![0.0; COUNT.pow(2)];
+        for (i, z) in zs.iter_mut().take(COUNT).enumerate() {
+            *z = (i % 2) as f32;
+        }
+
+        let tile = TileId {
+            root: 0,
+            path: 0b001_0000_0000_0000_0,
+        };
+        stitch_edges(tile, &mut zs, [None, None, Some(0), None]);
+        assert!(zs[..COUNT - 1].iter().all(|z| *z == 0.0));
+        assert_eq!(zs[COUNT - 1], 1.0); // corner is never moved
+
+        // the y-offset child has the coarse samples on the odd indices of the west edge
+        let mut zs = vec![0.0; COUNT.pow(2)];
+        for (i, z) in edge_indices(0).into_iter().enumerate() {
+            zs[z] = (i % 2) as f32;
+        }
+
+        let tile = TileId {
+            root: 0,
+            path: 0b001_0100_0000_0000_0,
+        };
+        stitch_edges(tile, &mut zs, [Some(0), None, None, None]);
+        let west = edge_indices(0).map(|i| zs[i]);
+        assert_eq!(west[0], 0.0);
+        assert!(west[1..].iter().all(|z| *z == 1.0));
+    }
+
+    #[test]
+    fn skirts_testing() {
+        let extents = world();
+        let tile = TileId {
+            root: 0,
+            path: 0b001_0000_0000_0000_0,
+        }
+        .as_num();
+        let zs = vec![0.01; COUNT.pow(2)];
+
+        let x = VertexData::fill_vertex_data_from_tile_zs_seamed(
+            &extents,
+            tile,
+            zs.clone(),
+            EdgeSeam::None,
+            &[0, 1, 1, 1],
+        );
+        let y = VertexData::fill_vertex_data_from_tile_zs_seamed(
+            &extents,
+            tile,
+            zs,
+            EdgeSeam::Skirt,
+            &[0, 1, 1, 1],
+        );
+
+        assert_eq!(y.positions.len(), x.positions.len() + COUNT * 3);
+        assert_eq!(y.normals.len(), y.positions.len());
+        assert_eq!(y.indices.len(), x.indices.len() + (COUNT - 1) * 6);
+
+        // skirt hangs a 32m LOD below the surface
+        let last = y.positions.len() - 2;
+        assert!((y.positions[last] - (0.01 - 32.0 / 4064.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rtin_vertex_data_testing() {
+        let extents = world();
+        let zs = vec![0.01; COUNT.pow(2)];
+
+        let smooth = VertexData::fill_vertex_data_from_tile_zs_smooth(&extents, 0, zs.clone());
+        let rtin = VertexData::fill_vertex_data_from_tile_zs_rtin(&extents, 0, zs, 0.1);
+
+        assert!(rtin.indices.len() * 10 < smooth.indices.len());
+        assert!(rtin.positions.len() < smooth.positions.len());
+        assert_eq!(rtin.normals.len(), rtin.positions.len());
+        // faces point up
+        assert!(rtin
+            .normals
+            .chunks_exact(3)
+            .all(|n| (n[1] - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn flat_vertex_data_testing() {
+        let extents = world();
+        let mut zs = (0..COUNT.pow(2))
+            .map(|i| ((i % COUNT) as f32 * 0.1).sin() * 0.01)
+            .collect::<Vec<_>>();
+        zs[10] = f32::NAN; // partial cells around a null
+
+        let smooth = VertexData::fill_vertex_data_from_tile_zs_smooth(&extents, 0, zs.clone());
+        let flat = VertexData::fill_vertex_data_from_tile_zs_flat(&extents, 0, zs);
+
+        assert_eq!(flat.positions.len(), smooth.indices.len() * 3);
+        assert_eq!(flat.normals.len(), flat.positions.len());
+        assert_eq!(flat.indices.len(), smooth.indices.len());
+
+        // every vertex of a face shares the face normal
+        for n in flat.normals.chunks_exact(9) {
+            assert_eq!(n[..3], n[3..6]);
+            assert_eq!(n[..3], n[6..]);
+        }
+    }
+
+    #[test]
+    fn edge_data_testing() {
+        let extents = world();
+        let mut zs = vec![0.01; COUNT.pow(2)];
+
+        let n = COUNT - 1;
+        let all = EdgeData::from_tile_zs(&extents, 0, zs.clone(), false);
+        // horizontal, vertical, and a diagonal per cell
+        assert_eq!(all.indices.len(), (2 * n * COUNT + n * n) * 2);
+
+        let outline = EdgeData::from_tile_zs(&extents, 0, zs.clone(), true);
+        assert_eq!(outline.indices.len(), 4 * n * 2);
+
+        // a null in the middle leaves a diamond hole (the partial cells keep a triangle each)
+        zs[64 * COUNT + 64] = f32::NAN;
+        let outline = EdgeData::from_tile_zs(&extents, 0, zs, true);
+        assert_eq!(outline.indices.len(), (4 * n + 4) * 2);
+    }
+
+    #[test]
+    fn colour_ramp_vertex_data_testing() {
+        let extents = Extents3 {
+            origin: [0.0, 0.0, 1000.0].into(),
+            size: [4064.0, 4064.0, 100.0].into(),
+        };
+        let zs = vec![0.01; COUNT.pow(2)];
+        let mut vd = VertexData::fill_vertex_data_from_tile_zs_smooth(&extents, 0, zs);
+        assert!(vd.colors.is_empty());
+
+        let ramp = ColourRamp::named("greys", 1000.0, 1100.0, false).unwrap();
+        vd.apply_colour_ramp(&extents, &ramp);
+
+        assert_eq!(vd.colors.len(), vd.positions.len() / 3 * 4);
+        // RL is 1000 + 0.01 * 4064
+        let c = vd.colors[0];
+        assert!((c - 0.4064).abs() < 1e-4, "{c}");
+    }
+
+    #[test]
+    fn smooth_winding_is_consistent() {
+        let extents = world();
+        // scatter nulls so every partial cell case is hit
+        let zs = (0..COUNT.pow(2))
+            .map(|i| if i % 7 == 0 { f32::NAN } else { 0.01 })
+            .collect::<Vec<_>>();
+        let vd = VertexData::fill_vertex_data_from_tile_zs_smooth(&extents, 0, zs);
+
+        let pos = |i: u32| {
+            let i = i as usize * 3;
+            [vd.positions[i], vd.positions[i + 2]] // world x/y
+        };
+        for f in vd.indices.chunks_exact(3) {
+            let [a, b, c] = [pos(f[0]), pos(f[1]), pos(f[2])];
+            let orient = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+            assert!(orient > 0.0, "face {f:?} winds clockwise");
+        }
+        assert!(vd.normals.chunks_exact(3).all(|n| n[1] > 0.99));
+    }
+
+    #[test]
+    fn double_sided_testing() {
+        let extents = world();
+        let zs = vec![0.01; COUNT.pow(2)];
+        let mut vd = VertexData::fill_vertex_data_from_tile_zs_smooth(&extents, 0, zs);
+        let (np, ni) = (vd.positions.len(), vd.indices.len());
+
+        vd.make_double_sided();
+        vd.make_double_sided(); // no-op
+
+        assert_eq!(vd.positions.len(), np * 2);
+        assert_eq!(vd.normals.len(), np * 2);
+        assert_eq!(vd.indices.len(), ni * 2);
+        assert_eq!(vd.facing.len(), np / 3 * 2);
+        assert_eq!(vd.facing[0], 1.0);
+        assert_eq!(vd.facing[np / 3], -1.0);
+        assert_eq!(vd.normals[np + 1], -1.0);
+
+        // the underside faces wind the other way
+        let (f, u) = (&vd.indices[..3], &vd.indices[ni..ni + 3]);
+        let n = (np / 3) as u32;
+        assert_eq!(u, [f[0] + n, f[2] + n, f[1] + n]);
+    }
+
+    #[test]
+    fn image_uvs_testing() {
+        let extents = Extents3 {
+            origin: [1000.0, 2000.0, 0.0].into(),
+            size: [4064.0, 4064.0, 100.0].into(),
+        };
+        let zs = vec![0.01; COUNT.pow(2)];
+        let mut vd = VertexData::fill_vertex_data_from_tile_zs_smooth(&extents, 0, zs);
+
+        let img = GeoImage::new(1000.0, 2000.0, 5064.0, 6064.0, 100, 100).unwrap();
+        vd.apply_image_uvs(&extents, &img);
+
+        assert_eq!(vd.uvs.len(), vd.positions.len() / 3 * 2);
+        assert_eq!(vd.uvs[..2], [0.0, 0.0]);
+        let last = vd.uvs.len() - 2;
+        assert!(vd.uvs[last..].iter().all(|x| (x - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn screen_space_error_lods() {
+        let world = world();
+        let viewbox = whole_world_viewbox(1.0);
+        // low over the south-west corner
+        let camera = Camera::new(0.0, 0.05, 0.0, 1.0, 1000.0).unwrap();
+
+        let mut tiles = ViewableTiles::new(&world);
+        tiles.update_with_camera(&viewbox, &camera);
+        let lvl_at = |tiles: &ViewableTiles, p: Point2| {
+            tiles
+                .in_view_tiles()
+                .into_iter()
+                .map(TileId::from_num)
+                .find(|t| contains_pt(&t.extents(&world), p))
+                .unwrap()
+                .lod_lvl()
+        };
+
+        let near = lvl_at(&tiles, [10.0, 10.0]);
+        let far = lvl_at(&tiles, [4000.0, 4000.0]);
+        assert!(near > far, "{near} {far}");
+
+        // the in view tiles still cover the root exactly
+        let area = tiles
+            .in_view_tiles()
+            .into_iter()
+            .map(|t| TileId::from_num(t).extents(&world).size)
+            .map(|[w, h]| w * h)
+            .sum::<f64>();
+        assert!((area - 4064.0f64.powi(2)).abs() < 1e-3);
+
+        // a bigger budget gives coarser tiles
+        tiles.set_pixel_error(1000.0);
+        tiles.update_with_camera(&viewbox, &camera);
+        assert!(lvl_at(&tiles, [10.0, 10.0]) < near);
+
+        // a camera 81 m up, within the world's z range, over data which is flat at 0 m
+        let camera = Camera::new(0.1, 0.02, 0.1, 1.0, 1000.0).unwrap();
+        let p = [406.4, 406.4];
+        let path = (0..=MAX_DEPTH)
+            .filter_map(|lvl| TileId::at(&world, p, lvl))
+            .map(|t| t.as_num())
+            .collect::<Vec<_>>();
+        let mut index = TileIndex::new();
+        let flat = TileSample::from_zs(vec![0.0; COUNT.pow(2)]).unwrap();
+        for t in &path {
+            index.insert(*t, &flat);
+        }
+        let mut tiles = ViewableTiles::new(&world);
+        tiles.set_pixel_error(50.0);
+        // without the z ranges, the tiles' boxes reach up to the camera
+        tiles.set_object_tiles("a", &path);
+        tiles.update_with_camera(&viewbox, &camera);
+        assert_eq!(lvl_at(&tiles, p), MAX_DEPTH);
+        // with them, the distance down to the data is used
+        tiles.set_object_index("a", &index);
+        tiles.update_with_camera(&viewbox, &camera);
+        assert!(lvl_at(&tiles, p) < MAX_DEPTH - 1);
+
+        assert!(Camera::new(0.0, 0.0, 0.0, 0.0, 1000.0).is_err());
+        assert!(Camera::new(0.0, 0.0, 0.0, 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn tile_budget_and_priority() {
+        let world = world();
+        let viewbox = whole_world_viewbox(1e-6);
+
+        let mut tiles = ViewableTiles::new(&world);
+        tiles.update(&viewbox);
+        assert_eq!(tiles.in_view_tiles().len(), 4096);
+
+        tiles.set_tile_budget(10);
+        tiles.update(&viewbox);
+        let inview = tiles.in_view_tiles();
+        assert!(inview.len() <= 10 && inview.len() > 4, "{}", inview.len());
+        let area = inview
+            .iter()
+            .map(|t| TileId::from_num(*t).extents(&world).size)
+            .map(|[w, h]| w * h)
+            .sum::<f64>();
+        assert!((area - 4064.0f64.powi(2)).abs() < 1e-3);
+
+        // the centre tiles come first and are the finest
+        let first = TileId::from_num(inview[0]);
+        let last = TileId::from_num(*inview.last().unwrap());
+        let xs = first.extents(&world);
+        let [x0, y0] = xs.origin;
+        let [x1, y1] = xs.max();
+        assert!(x0 <= 2032.0 && 2032.0 <= x1 && y0 <= 2032.0 && 2032.0 <= y1);
+        assert!(first.lod_lvl() >= last.lod_lvl());
+
+        // the roots are kept regardless
+        tiles.set_tile_budget(0);
+        tiles.update(&viewbox);
+        assert_eq!(tiles.in_view_tiles(), vec![0]);
+    }
+
+    #[test]
+    fn hysteresis_and_change_sets() {
+        let world = world();
+        // a view box over the whole world giving a LOD resolution of `res`
+        let viewbox = |res: f64| whole_world_viewbox((res * 200.0 / 4064.0).powi(2));
+        let max_lvl = |tiles: &ViewableTiles| {
+            tiles
+                .in_view_tiles()
+                .into_iter()
+                .map(|t| TileId::from_num(t).lod_lvl())
+                .max()
+                .unwrap()
+        };
+
+        let mut tiles = ViewableTiles::new(&world);
+        tiles.update(&viewbox(8.5));
+        assert_eq!(max_lvl(&tiles), 2);
+        assert_eq!(tiles.added().len(), tiles.in_view_tiles().len());
+        assert!(tiles.removed().is_empty() && tiles.kept().is_empty());
+
+        // within the band, so not refined
+        tiles.update(&viewbox(7.5));
+        assert_eq!(max_lvl(&tiles), 2);
+        assert!(tiles.added().is_empty() && tiles.removed().is_empty());
+
+        tiles.update(&viewbox(7.0));
+        assert_eq!(max_lvl(&tiles), 3);
+        assert_eq!(tiles.added().len(), 64);
+        assert_eq!(tiles.removed().len(), 16);
+
+        // coarsening back within the band keeps the refined tiles
+        tiles.update(&viewbox(8.5));
+        assert_eq!(max_lvl(&tiles), 3);
+        assert!(tiles.added().is_empty() && tiles.removed().is_empty());
+        assert_eq!(tiles.kept().len(), 64);
+
+        tiles.set_hysteresis(0.0);
+        tiles.update(&viewbox(8.5));
+        assert_eq!(max_lvl(&tiles), 2);
+        assert_eq!(tiles.removed().len(), 64);
+    }
+
+    #[test]
+    fn occupied_tiles_only() {
+        let world = world();
+        // LOD level 2 over the whole world
+        let viewbox = whole_world_viewbox((8.5 * 200.0 / 4064.0f64).powi(2));
+
+        let root = TileId::from_num(0);
+        let [sw, se, ..] = root.children();
+        let sw_sw = sw.children()[0];
+
+        let mut tiles = ViewableTiles::new(&world);
+        tiles.update(&viewbox);
+        assert_eq!(tiles.in_view_tiles().len(), 16);
+
+        // no tile may be drawn over another
+        let overlaps = |v: &[u32]| {
+            v.iter().any(|&t| {
+                std::iter::successors(TileId::from_num(t).parent(), |x| x.parent())
+                    .any(|x| v.contains(&x.as_num()))
+            })
+        };
+
+        // the data only goes to LOD 1 in the south-east, which is kept rather than refined
+        let a = [root, sw, sw_sw, se].map(|t| t.as_num());
+        tiles.set_object_tiles("a", &a);
+        tiles.update(&viewbox);
+        let mut v = tiles.in_view_tiles();
+        v.sort_unstable();
+        let mut want = vec![sw_sw.as_num(), se.as_num()];
+        want.sort_unstable();
+        assert_eq!(v, want);
+        assert!(!overlaps(&v));
+
+        // the ancestors without data are refined to reach the data
+        tiles.set_object_tiles("a", &[sw_sw.as_num()]);
+        tiles.update(&viewbox);
+        assert_eq!(tiles.in_view_tiles(), vec![sw_sw.as_num()]);
+
+        // only the south-east has data, so the other subtrees are skipped
+        tiles.set_object_tiles("b", &se.children().map(|t| t.as_num()));
+        tiles.remove_object("a");
+        tiles.update(&viewbox);
+        let mut v = tiles.in_view_tiles();
+        v.sort_unstable();
+        let mut want = se.children().map(|t| t.as_num()).to_vec();
+        want.sort_unstable();
+        assert_eq!(v, want);
+        assert!(!overlaps(&v));
+
+        tiles.remove_object("b");
+        tiles.update(&viewbox);
+        assert_eq!(tiles.in_view_tiles().len(), 16);
+    }
+
+    #[test]
+    fn frustum_culling() {
+        let world = world();
+        // a level camera at `eye` looking north (+z), with a `fov` square view
+        let view_proj = |eye: geom::Point3, fov: f64| {
+            let (n, f) = (0.001, 1000.0);
+            let s = (fov * 0.5).tan().recip();
+            let [x, y, z] = eye;
+            #[rustfmt::skip]
+            let m = [
+                s, 0.0, 0.0, 0.0,
+                0.0, s, 0.0, 0.0,
+                0.0, 0.0, (f + n) / (f - n), 1.0,
+                -x * s, -y * s, -z * (f + n) / (f - n) - 2.0 * f * n / (f - n), -z,
+            ];
+            m
+        };
+
+        // looking at the horizon from the middle of the world
+        let eye = [0.5, 0.01, 0.5];
+        let vb = Viewbox::from_view_projection(&world, &view_proj(eye, 1.5)).unwrap();
+        let f = vb.frustum.unwrap();
+        // the far plane is pulled in to the world's far corner
+        let far = f.corners().unwrap()[4..]
+            .iter()
+            .map(|p| p[2] - eye[2])
+            .fold(0.0, f64::max);
+        assert!((far - 0.5).abs() < 1e-6, "{far}");
+        assert!(vb.max_ps.iter().all(|p| p.iter().all(|x| x.is_finite())));
+
+        let mut tiles = ViewableTiles::new(&world);
+        let cam = Camera::new(eye[0], eye[1], eye[2], 1.5, 500.0).unwrap();
+        tiles.update_with_camera(&vb, &cam);
+        let v = tiles.in_view_tiles();
+        assert!(!v.is_empty());
+        // nothing behind the camera
+        for t in v {
+            let xs = TileId::from_num(t).extents(&world);
+            assert!(xs.max()[1] > 2032.0, "{xs:?}");
+        }
+
+        // a tile's z range above a narrow view is culled
+        let f = Viewbox::from_view_projection(&world, &view_proj(eye, 0.2))
+            .unwrap()
+            .frustum
+            .unwrap();
+        let t = TileId::at(&world, [2040.0, 2040.0], MAX_DEPTH).unwrap();
+        let (min, max) = render_box(&world, t, None);
+        assert!(f.intersects_box(min, max));
+        let (min, max) = render_box(&world, t, Some([80.0, 100.0]));
+        assert!(!f.intersects_box(min, max));
+
+        // a level orthographic camera looking east from the middle of the world reaches the
+        // far side, but not behind it
+        let east = [0.5, 0.0125, 0.5, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let vb = Viewbox::orthographic(&world, &east, &[-0.05, 0.05, -0.01, 0.01]).unwrap();
+        assert!(vb
+            .min_ps
+            .iter()
+            .chain(&vb.max_ps)
+            .all(|p| p.iter().all(|x| x.is_finite())));
+        assert!(
+            vb.max_ps.iter().all(|p| (p[0] - 1.0).abs() < 1e-9),
+            "{vb:?}"
+        );
+        tiles.update(&vb);
+        let v = tiles
+            .in_view_tiles()
+            .into_iter()
+            .map(|t| TileId::from_num(t).extents(&world))
+            .collect::<Vec<_>>();
+        assert!(v.iter().all(|e| e.max()[0] >= 2032.0), "{v:?}");
+        assert!(v
+            .iter()
+            .all(|e| e.max()[1] >= 1828.0 && e.origin[1] <= 2236.0));
+        assert!(v.iter().any(|e| e.max()[0] == 4064.0));
+
+        assert!(Viewbox::from_view_projection(&world, &[0.0; 15]).is_err());
+        assert!(Viewbox::from_view_projection(&world, &[0.0; 16]).is_err());
+    }
+
+    #[test]
+    fn prefetch_ahead_of_panning() {
+        let world = world();
+        // a LOD level 4 view box over the world x range `x0-x1` (y 100-900)
+        let viewbox = |x0: f64, x1: f64| {
+            let s = 4064.0;
+            let ps = [
+                [x0 / s, 100.0 / s],
+                [x1 / s, 100.0 / s],
+                [x1 / s, 900.0 / s],
+                [x0 / s, 900.0 / s],
+            ];
+            Viewbox {
+                min_ps: ps,
+                max_ps: ps,
+                render_area: (2.1 * 200.0 / s).powi(2),
+                frustum: None,
+            }
+        };
+
+        let mut tiles = ViewableTiles::new(&world);
+        tiles.update(&viewbox(100.0, 900.0));
+        assert!(tiles.prefetch_tiles().is_empty());
+
+        // panning east 100 m per update
+        tiles.update(&viewbox(200.0, 1000.0));
+        let pf = tiles.prefetch_tiles();
+        assert!(!pf.is_empty());
+        let v = tiles.in_view_tiles();
+        for t in &pf {
+            assert!(!v.contains(t));
+            let xs = TileId::from_num(*t).extents(&world);
+            assert_eq!(TileId::from_num(*t).lod_lvl(), 4);
+            assert!(xs.origin[0] >= 1016.0, "{xs:?}");
+        }
+
+        // updating the same view (eg on loading data) does not slow the estimate
+        tiles.update(&viewbox(200.0, 1000.0));
+        assert_eq!(tiles.prefetch_tiles(), pf);
+
+        // an explicit velocity, westward so nothing new comes into view
+        tiles.set_velocity(-100.0 / 4064.0, 0.0, 0.0);
+        tiles.update(&viewbox(200.0, 1000.0));
+        assert!(tiles.prefetch_tiles().is_empty());
+
+        tiles.clear_velocity();
+        tiles.set_prefetch_frames(0);
+        tiles.update(&viewbox(300.0, 1100.0));
+        assert!(tiles.prefetch_tiles().is_empty());
+    }
+
+    #[test]
+    fn orthographic_views() {
+        let world = world();
+        let quarter = [-0.125, 0.125, -0.125, 0.125];
+
+        // plan view, looking down with north up
+        let plan = [0.5, 1.0, 0.5, 0.0, -1.0, 0.0, 0.0, 0.0, 1.0];
+        let vb = Viewbox::orthographic(&world, &plan, &quarter).unwrap();
+        assert!((vb.render_area - 0.0625).abs() < 1e-9);
+        let xs = Extents2::from_iter(vb.min_ps.into_iter().chain(vb.max_ps));
+        assert!(xs.origin.sub([0.375, 0.375]).mag() < 1e-9, "{xs:?}");
+        assert!(xs.size.sub([0.25, 0.25]).mag() < 1e-9, "{xs:?}");
+
+        let mut tiles = ViewableTiles::new(&world);
+        tiles.update(&vb);
+        let v = tiles.in_view_tiles();
+        assert!(v.iter().all(|t| TileId::from_num(*t).lod_lvl() == 3));
+        assert!(v.iter().all(|t| {
+            let e = TileId::from_num(*t).extents(&world);
+            e.max()[0] >= 1524.0 && e.origin[0] <= 2540.0
+        }));
+
+        // a section looking north along x = 2032, covering the whole length of the world
+        let section = [0.5, 0.01, -0.1, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0];
+        let vb = Viewbox::orthographic(&world, &section, &[-0.01, 0.01, -0.01, 0.02]).unwrap();
+        tiles.update(&vb);
+        let v = tiles
+            .in_view_tiles()
+            .into_iter()
+            .map(|t| TileId::from_num(t).extents(&world))
+            .collect::<Vec<_>>();
+        assert!(v
+            .iter()
+            .all(|e| e.max()[0] >= 1991.0 && e.origin[0] <= 2073.0));
+        assert!(v.iter().any(|e| e.max()[1] == 4064.0));
+        assert!(v.iter().any(|e| e.origin[1] == 0.0));
+
+        let ortho = |c: &[f64], b: &[f64]| Viewbox::orthographic(&world, c, b).is_err();
+        assert!(ortho(&plan[..8], &quarter));
+        assert!(ortho(&plan, &[0.1, -0.1, 0.0, 1.0]));
+        assert!(ortho(
+            &[0.5, 1.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            &quarter
+        ));
+        assert!(ortho(
+            &[0.5, 1.0, 0.5, 0.0, -1.0, 0.0, 0.0, 2.0, 0.0],
+            &quarter
+        ));
+    }
+
+    #[test]
+    fn viewport_validation() {
+        let world = world();
+        let p = Point3::new;
+        let down = p(0.0, -1.0, 0.0);
+        let (bl, br, tr, tl) = (
+            p(0.25, 1.0, 0.25),
+            p(0.75, 1.0, 0.25),
+            p(0.75, 1.0, 0.75),
+            p(0.25, 1.0, 0.75),
+        );
+
+        let vb = Viewbox::from_viewport(&world, &down, &bl, &br, &tr, &tl).unwrap();
+        assert!((vb.render_area - 0.25).abs() < 1e-9);
+        assert_eq!(
+            vb.min_ps,
+            [[0.25, 0.25], [0.75, 0.25], [0.75, 0.75], [0.25, 0.75]]
+        );
+
+        let zero = Point3::zero();
+        assert!(Viewbox::from_viewport(&world, &zero, &bl, &br, &tr, &tl).is_err());
+        // collapsed to a line
+        assert!(Viewbox::from_viewport(&world, &down, &bl, &br, &br, &bl).is_err());
+        let nan = p(f64::NAN, 1.0, 0.0);
+        assert!(Viewbox::from_viewport(&world, &down, &nan, &br, &tr, &tl).is_err());
+
+        // a level camera direction never reaches the z planes, so it is nudged downwards to keep
+        // the view box finite
+        #[rustfmt::skip]
+        let vb = Viewbox::calculate(&world, &[
+            0.0, 0.0, 1.0,
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0,
+        ])
+        .unwrap();
+        assert!(vb
+            .min_ps
+            .iter()
+            .chain(&vb.max_ps)
+            .all(|p| p.iter().all(|x| x.is_finite())));
+
+        assert!(Viewbox::calculate(&world, &[0.0; 14]).is_err());
+        assert!(Viewbox::calculate(&world, &[0.0; 15]).is_err());
+    }
+
+    #[test]
+    fn world_render_roundtrip() {
+        let xs = Extents3 {
+            origin: [1000.0, 2000.0, 100.0].into(),
+            size: [400.0, 200.0, 50.0].into(),
+        };
+
+        let r = xs.world_to_render(1200.0, 2100.0, 120.0);
+        assert_eq!((r.x, r.y, r.z), (0.5, 0.05, 0.25));
+        let w = xs.render_to_world(r.x, r.y, r.z);
+        assert_eq!((w.x, w.y, w.z), (1200.0, 2100.0, 120.0));
+
+        let world = [1000.0, 2000.0, 100.0, 1400.0, 2200.0, 150.0];
+        let render = xs.world_to_render_flat(&world).unwrap();
+        assert_eq!(render, vec![0.0, 0.0, 0.0, 1.0, 0.125, 0.5]);
+        assert_eq!(xs.render_to_world_flat(&render).unwrap(), world);
+        assert!(xs.world_to_render_flat(&[0.0; 4]).is_err());
+        assert!(xs.render_to_world_flat(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn camera_breaks_centre_ties() {
+        use std::collections::BinaryHeap;
+
+        let [a, b, c, _] = TileId::from_num(0).children();
+        let ring = tile_size(MAX_DEPTH as usize);
+        let mut heap = BinaryHeap::new();
+        // `a` is slightly closer to the centre, but within the same ring as `b`, which is
+        // nearer the camera
+        heap.push(Prioritised::new(ring * 1.2, 50.0, a));
+        heap.push(Prioritised::new(ring * 1.5, 20.0, b));
+        // `c` is in an inner ring, so comes first however far from the camera
+        heap.push(Prioritised::new(ring * 0.9, 500.0, c));
+
+        let order = std::iter::from_fn(|| heap.pop().map(|p| p.3)).collect::<Vec<_>>();
+        assert_eq!(order, vec![c, b, a]);
+
+        // without a camera, the exact centre distance decides
+        let mut heap = BinaryHeap::new();
+        heap.push(Prioritised::new(ring * 1.5, 0.0, b));
+        heap.push(Prioritised::new(ring * 1.2, 0.0, a));
+        assert_eq!(heap.pop().map(|p| p.3), Some(a));
+    }
 }
@@ -0,0 +1,236 @@
+//! Measuring tape: distances, bearings and grades between picked points.
+use super::*;
+
+/// Measurements along a path of picked **world** points.
+///
+/// Each _leg_ is between consecutive points, the totals are over the whole path (with the
+/// bearing and grade taken from the first to the last point). Bearings are in degrees
+/// clockwise from north, grades are percentages and dips are degrees (both positive uphill).
+/// Grades are undefined without a horizontal distance (a vertical or zero length leg), and are
+/// `NaN` rather than infinite.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct Measurement {
+    pts: Vec<geom::Point3>,
+    surface: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl Measurement {
+    /// Measure a path of flat world XYZ coordinates (at least 2 points).
+    pub fn new(xyzs: &[f64]) -> Result<Measurement, String> {
+        if !xyzs.chunks_exact(3).remainder().is_empty() {
+            return Err("coordinates must be x,y,z triples".to_string());
+        }
+        if xyzs.len() < 6 || xyzs.iter().any(|x| !x.is_finite()) {
+            return Err("measurement needs at least 2 finite points".to_string());
+        }
+
+        Ok(Self {
+            pts: xyzs.chunks_exact(3).map(|x| [x[0], x[1], x[2]]).collect(),
+            surface: Vec::new(),
+        })
+    }
+
+    /// The path as flat world XY coordinates, for sampling a [`Section`] along it.
+    pub fn polyline(&self) -> Vec<f64> {
+        self.pts.iter().flat_map(|[x, y, _]| [*x, *y]).collect()
+    }
+
+    pub fn horizontals(&self) -> Vec<f64> {
+        self.legs().map(horizontal).collect()
+    }
+
+    pub fn slopes(&self) -> Vec<f64> {
+        self.legs().map(|(a, b)| b.sub(a).mag()).collect()
+    }
+
+    pub fn bearings(&self) -> Vec<f64> {
+        self.legs().map(bearing).collect()
+    }
+
+    pub fn grades(&self) -> Vec<f64> {
+        self.legs().map(grade).collect()
+    }
+
+    pub fn dips(&self) -> Vec<f64> {
+        self.legs().map(dip).collect()
+    }
+
+    /// The height difference of each leg (end less start).
+    pub fn dzs(&self) -> Vec<f64> {
+        self.legs().map(|(a, b)| b[2] - a[2]).collect()
+    }
+
+    pub fn total_horizontal(&self) -> f64 {
+        self.horizontals().into_iter().sum()
+    }
+
+    pub fn total_slope(&self) -> f64 {
+        self.slopes().into_iter().sum()
+    }
+
+    pub fn total_dz(&self) -> f64 {
+        self.ends().1[2] - self.ends().0[2]
+    }
+
+    pub fn total_bearing(&self) -> f64 {
+        bearing(self.ends())
+    }
+
+    /// The overall grade, the total height difference over the total horizontal distance.
+    pub fn total_grade(&self) -> f64 {
+        percent(self.total_dz(), self.total_horizontal())
+    }
+
+    pub fn total_dip(&self) -> f64 {
+        self.total_dz().atan2(self.total_horizontal()).to_degrees()
+    }
+
+    /// Measure along a surface's profile under the path, for surface-following distances.
+    ///
+    /// The `section` should be sampled along [`Self::polyline`], either exactly or from the
+    /// tile grids ([`SectionSampler`]). Gaps in the surface are bridged with straight lines.
+    pub fn follow_surface(&mut self, section: &Section) {
+        // the chainage at the end of each leg
+        let ends = self
+            .horizontals()
+            .into_iter()
+            .scan(0.0, |c, h| {
+                *c += h;
+                Some(*c)
+            })
+            .collect::<Vec<_>>();
+
+        let mut surface = vec![0.0; ends.len()];
+        let pts = section
+            .chainages()
+            .into_iter()
+            .zip(section.zs())
+            .filter(|(_, z)| z.is_finite())
+            .collect::<Vec<_>>();
+        for w in pts.windows(2) {
+            let ((c0, z0), (c1, z1)) = (w[0], w[1]);
+            let mid = (c0 + c1) * 0.5;
+            let leg = ends
+                .iter()
+                .position(|e| mid <= *e)
+                .unwrap_or(ends.len() - 1);
+            surface[leg] += (c1 - c0).hypot(z1 - z0);
+        }
+
+        self.surface = surface;
+    }
+
+    /// The surface-following distance of each leg, empty if no surface has been followed.
+    pub fn surfaces(&self) -> Vec<f64> {
+        self.surface.clone()
+    }
+
+    /// The total surface-following distance, `NaN` if no surface has been followed.
+    pub fn total_surface(&self) -> f64 {
+        if self.surface.is_empty() {
+            f64::NAN
+        } else {
+            self.surface.iter().sum()
+        }
+    }
+}
+
+impl Measurement {
+    fn legs(&self) -> impl Iterator<Item = (geom::Point3, geom::Point3)> + '_ {
+        self.pts.windows(2).map(|x| (x[0], x[1]))
+    }
+
+    fn ends(&self) -> (geom::Point3, geom::Point3) {
+        (self.pts[0], self.pts[self.pts.len() - 1])
+    }
+}
+
+fn horizontal((a, b): (geom::Point3, geom::Point3)) -> f64 {
+    (b[0] - a[0]).hypot(b[1] - a[1])
+}
+
+fn bearing((a, b): (geom::Point3, geom::Point3)) -> f64 {
+    (b[0] - a[0])
+        .atan2(b[1] - a[1])
+        .to_degrees()
+        .rem_euclid(360.0)
+}
+
+fn grade((a, b): (geom::Point3, geom::Point3)) -> f64 {
+    percent(b[2] - a[2], horizontal((a, b)))
+}
+
+/// The rise over the run as a percentage, `NaN` without a run.
+fn percent(rise: f64, run: f64) -> f64 {
+    if run > 0.0 {
+        rise / run * 100.0
+    } else {
+        f64::NAN
+    }
+}
+
+fn dip((a, b): (geom::Point3, geom::Point3)) -> f64 {
+    (b[2] - a[2]).atan2(horizontal((a, b))).to_degrees()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legs_and_totals() {
+        // 40 east rising 30, then 30 north level
+        let m = Measurement::new(&[0.0, 0.0, 0.0, 40.0, 0.0, 30.0, 40.0, 30.0, 30.0]).unwrap();
+        assert_eq!(m.horizontals(), vec![40.0, 30.0]);
+        assert_eq!(m.slopes(), vec![50.0, 30.0]);
+        assert_eq!(m.bearings(), vec![90.0, 0.0]);
+        assert_eq!(m.grades(), vec![75.0, 0.0]);
+        assert_eq!(m.dzs(), vec![30.0, 0.0]);
+        assert!((m.dips()[0] - 36.8699).abs() < 1e-4);
+
+        assert_eq!(m.total_horizontal(), 70.0);
+        assert_eq!(m.total_slope(), 80.0);
+        assert_eq!(m.total_dz(), 30.0);
+        assert!((m.total_bearing() - 53.1301).abs() < 1e-4);
+        assert!((m.total_grade() - 30.0 / 70.0 * 100.0).abs() < 1e-9);
+        assert!(m.total_surface().is_nan());
+
+        assert_eq!(m.polyline(), vec![0.0, 0.0, 40.0, 0.0, 40.0, 30.0]);
+        assert!(Measurement::new(&[0.0; 3]).is_err());
+        assert!(Measurement::new(&[0.0; 7]).is_err());
+    }
+
+    #[test]
+    fn vertical_legs_have_no_grade() {
+        // straight up 10, then a repeated point
+        let m = Measurement::new(&[5.0, 5.0, 0.0, 5.0, 5.0, 10.0, 5.0, 5.0, 10.0]).unwrap();
+        assert_eq!(m.horizontals(), vec![0.0, 0.0]);
+        assert!(m.grades().iter().all(|g| g.is_nan()));
+        assert!(m.total_grade().is_nan());
+        assert_eq!(m.dips(), vec![90.0, 0.0]);
+        assert_eq!(m.total_dz(), 10.0);
+    }
+
+    #[test]
+    fn surface_following() {
+        let mut m = Measurement::new(&[0.0, 0.0, 0.0, 40.0, 0.0, 0.0, 40.0, 30.0, 0.0]).unwrap();
+
+        // a mesh with a 30 m high ridge along x = 20
+        let ridge = TriangleMeshSurface {
+            translate: Point3::zero(),
+            points: vec![
+                0., -10., 0., 20., -10., 30., 40., -10., 0., 0., 40., 0., 20., 40., 30., 40., 40.,
+                0.,
+            ],
+            indices: vec![0, 1, 4, 4, 3, 0, 1, 2, 5, 5, 4, 1],
+        };
+        m.follow_surface(&ridge.section(&m.polyline()).unwrap());
+
+        let s = m.surfaces();
+        assert!((s[0] - 2.0 * 20.0f64.hypot(30.0)).abs() < 1e-6, "{s:?}");
+        assert!((s[1] - 30.0).abs() < 1e-6, "{s:?}");
+        assert!((m.total_surface() - s[0] - s[1]).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,595 @@
+//! Ray picking against the stored surface data (rather than what the GPU has loaded).
+use super::*;
+
+/// Casts rays against objects' tile grids (or raw triangles) to find world coordinates.
+///
+/// Feed the picker the tiles of each object with [`Self::add_tile`], which can be any mix of
+/// LODs. Where tiles overlap, the finest LOD is used (a coarser tile is only hit where no
+/// finer tile is loaded). If an object's raw mesh has been supplied with [`Self::add_mesh`],
+/// its triangles are used instead of the tiles.
+/// Given an object's [`TileIndex`], the picker can also list the tiles a ray could hit, so
+/// only those need loading.
+#[wasm_bindgen]
+pub struct Picker {
+    extents: Extents3,
+    objs: Vec<PickObj>,
+}
+
+struct PickObj {
+    key: String,
+    /// tile -> (zs, world z range)
+    tiles: HashMap<u32, (Vec<f32>, [f64; 2])>,
+    /// the world z range of each loaded tile's subtree, empty subtrees are absent
+    bounds: HashMap<u32, [f64; 2]>,
+    mesh: Option<Vec<Tri>>,
+    index: TileIndex,
+    /// the world z range of each indexed tile's subtree
+    index_bounds: HashMap<u32, [f64; 2]>,
+}
+
+/// The nearest surface hit of a ray.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct PickHit {
+    point: Point3,
+    key: String,
+    tile: Option<u32>,
+    distance: f64,
+}
+
+#[wasm_bindgen]
+impl Picker {
+    pub fn new(extents: &Extents3) -> Self {
+        Self {
+            extents: *extents,
+            objs: Vec::new(),
+        }
+    }
+
+    /// Add a tile's `zs` (stored, render space) for the object.
+    pub fn add_tile(&mut self, key: &str, tile_idx: u32, zs: Vec<f32>) -> Result<(), String> {
+        let sample = TileSample::from_zs(zs)?;
+        let range = sample
+            .world_range(&self.extents)
+            .unwrap_or([f64::INFINITY, f64::NEG_INFINITY]);
+
+        let o = self.obj(key);
+        if o.tiles
+            .insert(tile_idx, (sample.into_zs(), range))
+            .is_some()
+        {
+            // the old range may no longer hold
+            o.rebuild_bounds();
+        } else {
+            expand_bounds(&mut o.bounds, tile_idx, range);
+        }
+        Ok(())
+    }
+
+    pub fn remove_tile(&mut self, key: &str, tile_idx: u32) {
+        if let Some(o) = self.objs.iter_mut().find(|o| o.key == key) {
+            if o.tiles.remove(&tile_idx).is_some() {
+                o.rebuild_bounds();
+            }
+        }
+    }
+
+    /// Use the raw triangles of the object for exact picking.
+    pub fn add_mesh(&mut self, key: &str, mesh: &TriangleMeshSurface) {
+        self.obj(key).mesh = Some(mesh.tris().collect());
+    }
+
+    pub fn remove_object(&mut self, key: &str) {
+        self.objs.retain(|o| o.key != key);
+    }
+
+    /// Set the object's tile index, see [`Self::candidate_tiles`].
+    pub fn set_index(&mut self, key: &str, index: &TileIndex) {
+        let mut bounds = HashMap::default();
+        index.subtree_ranges(&self.extents, &mut bounds);
+        let o = self.obj(key);
+        o.index = index.clone();
+        o.index_bounds = bounds;
+    }
+
+    /// The object's indexed tiles whose bounding boxes the `ray` (as [`Self::pick`]) passes
+    /// through, sorted by LOD level then tile.
+    ///
+    /// Load these tiles (at the LODs wanted) before picking, the rest cannot be hit.
+    pub fn candidate_tiles(&self, key: &str, ray: &[f64]) -> Result<Vec<u32>, String> {
+        let ray = self.ray(ray)?;
+        let Some(obj) = self.objs.iter().find(|o| o.key == key) else { return Ok(Vec::new()); };
+
+        let mut hits = Vec::new();
+        let mut open = TileId::roots(&self.extents);
+        while let Some(t) = open.pop() {
+            let Some(zr) = obj.index_bounds.get(&t.as_num()) else { continue; };
+            if ray.hits_box(t.extents(&self.extents), *zr) {
+                hits.push(t);
+                if !t.is_max_() {
+                    open.extend(t.children());
+                }
+            }
+        }
+
+        hits.sort_unstable_by_key(|t| (t.lod_lvl(), t.as_num()));
+        // ancestors of indexed tiles are also walked, keep only the indexed ones
+        Ok(hits
+            .into_iter()
+            .map(|t| t.as_num())
+            .filter(|t| obj.index.valid(*t).is_some())
+            .collect())
+    }
+
+    /// Cast a ray, returning the nearest hit over all objects.
+    ///
+    /// `ray` is 6 floats in **render space**: the origin then the direction.
+    pub fn pick(&self, ray: &[f64]) -> Result<Option<PickHit>, String> {
+        init_panic_hook();
+
+        let ray = self.ray(ray)?;
+        let hit = self
+            .objs
+            .iter()
+            .filter_map(|o| {
+                let (t, tile) = match &o.mesh {
+                    Some(tris) => ray.nearest(tris.iter()).map(|t| (t, None)),
+                    None => self.walk(&ray, o),
+                }?;
+                Some((t, o, tile))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(hit.map(|(t, o, tile)| PickHit {
+            point: ray.at(t).into(),
+            key: o.key.clone(),
+            tile,
+            distance: t,
+        }))
+    }
+}
+
+impl Picker {
+    fn obj(&mut self, key: &str) -> &mut PickObj {
+        let i = match self.objs.iter().position(|o| o.key == key) {
+            Some(i) => i,
+            None => {
+                self.objs.push(PickObj {
+                    key: key.to_string(),
+                    tiles: HashMap::default(),
+                    bounds: HashMap::default(),
+                    mesh: None,
+                    index: TileIndex::default(),
+                    index_bounds: HashMap::default(),
+                });
+                self.objs.len() - 1
+            }
+        };
+        &mut self.objs[i]
+    }
+
+    /// Reads a **render space** ray into world space.
+    fn ray(&self, ray: &[f64]) -> Result<Ray, String> {
+        let [ox, oy, oz, dx, dy, dz]: [f64; 6] = ray
+            .try_into()
+            .map_err(|_| format!("expecting 6 ray values, found {}", ray.len()))?;
+        let origin = geom::Point3::from(self.extents.render_to_world(ox, oy, oz));
+        // note the swap to Z-up
+        let dir = [dx, dz, dy];
+        if dir.mag() == 0.0 || !dir.mag().is_finite() {
+            return Err("ray direction must be non-zero".to_string());
+        }
+
+        Ok(Ray {
+            origin,
+            dir: dir.unit(),
+        })
+    }
+
+    /// Walk the tile tree, preferring hits on the finer tiles.
+    fn walk(&self, ray: &Ray, obj: &PickObj) -> Option<(f64, Option<u32>)> {
+        let world = &self.extents;
+
+        fn go(this: &Picker, ray: &Ray, obj: &PickObj, t: TileId) -> Option<(f64, Option<u32>)> {
+            let zr = *obj.bounds.get(&t.as_num())?;
+            if !ray.hits_box(t.extents(&this.extents), zr) {
+                return None;
+            }
+
+            let finer = (!t.is_max_())
+                .then(|| {
+                    t.children()
+                        .into_iter()
+                        .filter_map(|c| go(this, ray, obj, c))
+                        .min_by(|a, b| a.0.total_cmp(&b.0))
+                })
+                .flatten();
+
+            let coarse = obj.tiles.get(&t.as_num()).and_then(|(zs, zr)| {
+                ray.nearest_in_tile(&this.extents, t, zs, *zr)
+                    .map(|d| (d, Some(t.as_num())))
+            });
+
+            match (finer, coarse) {
+                // the finer tiles win where they cover the coarse hit, otherwise the coarse hit
+                // may be on ground the finer tiles do not reach
+                (Some(f), Some(c)) if f.0 > c.0 && !covered(this, obj, t, ray.at(c.0)) => Some(c),
+                (f, c) => f.or(c),
+            }
+        }
+
+        /// Whether the point is covered by a loaded tile finer than `t`.
+        fn covered(this: &Picker, obj: &PickObj, t: TileId, [x, y, _]: geom::Point3) -> bool {
+            (t.lod_lvl() + 1..=MAX_DEPTH)
+                .map_while(|lvl| TileId::at(&this.extents, [x, y], lvl))
+                .take_while(|c| obj.bounds.contains_key(&c.as_num()))
+                .any(|c| obj.tiles.contains_key(&c.as_num()))
+        }
+
+        TileId::roots(world)
+            .into_iter()
+            .filter_map(|t| go(self, ray, obj, t))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+}
+
+impl PickObj {
+    fn rebuild_bounds(&mut self) {
+        self.bounds.clear();
+        for (t, (_, range)) in &self.tiles {
+            expand_bounds(&mut self.bounds, *t, *range);
+        }
+    }
+}
+
+/// Expands the subtree z ranges of the tile and its ancestors to include `range`.
+fn expand_bounds(bounds: &mut HashMap<u32, [f64; 2]>, tile_idx: u32, [z0, z1]: [f64; 2]) {
+    let mut t = Some(TileId::from_num(tile_idx));
+    while let Some(x) = t {
+        let r = bounds
+            .entry(x.as_num())
+            .or_insert([f64::INFINITY, f64::NEG_INFINITY]);
+        if r[0] <= z0 && r[1] >= z1 && r[0] <= r[1] {
+            break;
+        }
+        *r = [r[0].min(z0), r[1].max(z1)];
+        t = x.parent();
+    }
+}
+
+#[wasm_bindgen]
+impl PickHit {
+    /// The hit in **world** space.
+    pub fn point(&self) -> Point3 {
+        self.point
+    }
+
+    /// The key of the object hit.
+    pub fn key(&self) -> String {
+        self.key.clone()
+    }
+
+    /// The tile hit, `undefined` if the raw triangles were hit.
+    pub fn tile(&self) -> Option<u32> {
+        self.tile
+    }
+
+    /// The distance from the ray origin, in world units.
+    pub fn distance(&self) -> f64 {
+        self.distance
+    }
+}
+
+/// A world space ray, with a unit direction.
+struct Ray {
+    origin: geom::Point3,
+    dir: geom::Point3,
+}
+
+impl Ray {
+    fn at(&self, t: f64) -> geom::Point3 {
+        self.origin.add(self.dir.scale(t))
+    }
+
+    /// The nearest triangle hit distance (Möller–Trumbore), ignoring hits behind the origin.
+    fn nearest<'a, I: Iterator<Item = &'a Tri>>(&self, tris: I) -> Option<f64> {
+        tris.filter_map(|[a, b, c]| {
+            let (e1, e2) = (b.sub(*a), c.sub(*a));
+            let p = xprod(self.dir, e2);
+            let det = dot(e1, p);
+            if det.abs() < 1e-12 {
+                return None;
+            }
+
+            let s = self.origin.sub(*a);
+            let u = dot(s, p) / det;
+            let q = xprod(s, e1);
+            let v = dot(self.dir, q) / det;
+            let t = dot(e2, q) / det;
+            (u >= 0.0 && v >= 0.0 && u + v <= 1.0 && t >= 0.0).then_some(t)
+        })
+        .min_by(|a, b| a.total_cmp(b))
+    }
+
+    /// The nearest hit on a tile's grid (of world z range `zr`), walking only the grid cells
+    /// the ray passes over, in order.
+    fn nearest_in_tile(
+        &self,
+        world: &Extents3,
+        t: TileId,
+        zs: &[f32],
+        zr: [f64; 2],
+    ) -> Option<f64> {
+        let xy = t.extents(world);
+        let [near, far] = self.clip_box(xy, zr)?;
+
+        let res = t.lod_res();
+        let (scaler, wz) = (world.max_dim(), world.origin.z);
+        let last = (COUNT - 2) as isize;
+        let node = |x: usize, y: usize| {
+            let z = zs[y * COUNT + x];
+            let [px, py] = xy.origin.add([x, y].map(|x| x as f64 * res));
+            z.is_finite().then_some([px, py, z as f64 * scaler + wz])
+        };
+
+        // the starting cell, and the ray distance to its next x and y cell boundaries
+        let p = self.at(near);
+        let mut cell =
+            [0, 1].map(|i| (((p[i] - xy.origin[i]) / res).floor() as isize).clamp(0, last));
+        let step = [0, 1].map(|i| if self.dir[i] < 0.0 { -1 } else { 1 });
+        let mut next = [0, 1].map(|i| {
+            let edge = cell[i] + isize::from(step[i] > 0);
+            let d = xy.origin[i] + edge as f64 * res - self.origin[i];
+            if self.dir[i] == 0.0 {
+                f64::INFINITY
+            } else {
+                d / self.dir[i]
+            }
+        });
+        let delta = [0, 1].map(|i| res / self.dir[i].abs());
+
+        loop {
+            let [x, y] = cell.map(|x| x as usize);
+            let [bl, br, tl, tr] =
+                [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)].map(|(x, y)| node(x, y));
+            // as the smooth meshing
+            let tris = match (bl, br, tl, tr) {
+                (Some(bl), Some(br), Some(tl), Some(tr)) => {
+                    [Some([bl, br, tr]), Some([tr, tl, bl])]
+                }
+                (Some(bl), Some(br), Some(tl), None) => [Some([tl, bl, br]), None],
+                (Some(bl), Some(br), None, Some(tr)) => [Some([bl, br, tr]), None],
+                (Some(bl), None, Some(tl), Some(tr)) => [Some([tr, tl, bl]), None],
+                (None, Some(br), Some(tl), Some(tr)) => [Some([br, tr, tl]), None],
+                _ => [None, None],
+            };
+            // the cells are walked in order, so the first hit is the nearest
+            if let Some(d) = self.nearest(tris.iter().flatten()) {
+                return Some(d);
+            }
+
+            let i = usize::from(next[1] < next[0]);
+            if next[i] > far {
+                return None;
+            }
+            cell[i] += step[i];
+            next[i] += delta[i];
+            if !(0..=last).contains(&cell[i]) {
+                return None;
+            }
+        }
+    }
+
+    /// Slab test against the box of the XY extents and z range.
+    fn hits_box(&self, xy: Extents2, zr: [f64; 2]) -> bool {
+        self.clip_box(xy, zr).is_some()
+    }
+
+    /// The ray's (near, far) distances through the box of the XY extents and z range.
+    fn clip_box(&self, xy: Extents2, [z0, z1]: [f64; 2]) -> Option<[f64; 2]> {
+        if z0 > z1 {
+            return None;
+        }
+
+        let [x0, y0] = xy.origin;
+        let [x1, y1] = xy.max();
+        let (mut near, mut far) = (0.0f64, f64::INFINITY);
+        for ((o, d), (lo, hi)) in
+            self.origin
+                .into_iter()
+                .zip(self.dir)
+                .zip([(x0, x1), (y0, y1), (z0, z1)])
+        {
+            if d == 0.0 {
+                if o < lo || o > hi {
+                    return None;
+                }
+            } else {
+                let (a, b) = ((lo - o) / d, (hi - o) / d);
+                near = near.max(a.min(b));
+                far = far.min(a.max(b));
+            }
+        }
+
+        (near <= far).then_some([near, far])
+    }
+}
+
+fn dot(a: geom::Point3, b: geom::Point3) -> f64 {
+    a.into_iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> Extents3 {
+        Extents3 {
+            origin: [0.0, 0.0, 100.0].into(),
+            size: [4064.0, 4064.0, 100.0].into(),
+        }
+    }
+
+    /// A ray straight down onto the world XY point, in render space.
+    fn down(x: f64, y: f64) -> Vec<f64> {
+        let s = 4064.0;
+        vec![x / s, 1.0, y / s, 0.0, -1.0, 0.0]
+    }
+
+    #[test]
+    fn picks_finest_tile() {
+        let mut p = Picker::new(&world());
+        assert!(p.pick(&down(10.0, 10.0)).unwrap().is_none());
+
+        // root at 10 m up, the south-west child at 20 m up
+        let flat = |z: f64| vec![(z / 4064.0) as f32; COUNT.pow(2)];
+        let child = TileId::from_num(0).children()[0];
+        p.add_tile("a", 0, flat(10.0)).unwrap();
+        p.add_tile("a", child.as_num(), flat(20.0)).unwrap();
+
+        let h = p.pick(&down(10.0, 10.0)).unwrap().unwrap();
+        assert_eq!(h.key(), "a");
+        assert_eq!(h.tile(), Some(child.as_num()));
+        let Point3 { x, y, z } = h.point();
+        assert!((x - 10.0).abs() < 1e-6 && (y - 10.0).abs() < 1e-6);
+        assert!((z - 120.0).abs() < 1e-3, "{z}");
+
+        // outside the child falls back to the root
+        let h = p.pick(&down(3000.0, 3000.0)).unwrap().unwrap();
+        assert_eq!(h.tile(), Some(0));
+        assert!((h.point().z - 110.0).abs() < 1e-3);
+
+        p.remove_tile("a", child.as_num());
+        let h = p.pick(&down(10.0, 10.0)).unwrap().unwrap();
+        assert_eq!(h.tile(), Some(0));
+
+        assert!(p.pick(&[0.0; 5]).is_err());
+        assert!(p.pick(&[0.0; 6]).is_err());
+    }
+
+    #[test]
+    fn picks_nearest_mesh() {
+        let sq = |z: f32| TriangleMeshSurface {
+            translate: Point3::zero(),
+            points: vec![0., 0., z, 100., 0., z, 100., 100., z, 0., 100., z],
+            indices: vec![0, 1, 2, 2, 3, 0],
+        };
+
+        let mut p = Picker::new(&world());
+        p.add_mesh("low", &sq(150.0));
+        p.add_mesh("high", &sq(180.0));
+
+        let h = p.pick(&down(50.0, 50.0)).unwrap().unwrap();
+        assert_eq!(h.key(), "high");
+        assert_eq!(h.tile(), None);
+        assert!((h.point().z - 180.0).abs() < 1e-9);
+
+        p.remove_object("high");
+        let h = p.pick(&down(50.0, 50.0)).unwrap().unwrap();
+        assert_eq!(h.key(), "low");
+        assert!((h.distance() - (4064.0 + 100.0 - 150.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn candidate_tiles_from_index() {
+        // tiles with heights from `lo` to `hi` metres above the world origin
+        let sample = |lo: f64, hi: f64| {
+            let zs = (0..COUNT.pow(2))
+                .map(|i| ((if i & 1 == 0 { lo } else { hi }) / 4064.0) as f32)
+                .collect();
+            TileSample::from_zs(zs).unwrap()
+        };
+        let [sw, se, ..] = TileId::from_num(0).children();
+
+        // the south-west child is rough, the south-east flat down low
+        let mut idx = TileIndex::new();
+        idx.insert(0, &sample(10.0, 10.0));
+        idx.insert(sw.as_num(), &sample(10.0, 90.0));
+        idx.insert(se.as_num(), &sample(10.0, 10.0));
+
+        let mut p = Picker::new(&world());
+        assert!(p
+            .candidate_tiles("a", &down(10.0, 10.0))
+            .unwrap()
+            .is_empty());
+        p.set_index("a", &idx);
+        assert_eq!(
+            p.candidate_tiles("a", &down(10.0, 10.0)).unwrap(),
+            vec![0, sw.as_num()]
+        );
+
+        // a level ray east at 150 m only passes through the rough tile's box
+        let s = 4064.0;
+        let ray = [0.0, 50.0 / s, 10.0 / s, 1.0, 0.0, 0.0];
+        assert_eq!(p.candidate_tiles("a", &ray).unwrap(), vec![0, sw.as_num()]);
+        let ray = [0.0, 95.0 / s, 10.0 / s, 1.0, 0.0, 0.0];
+        assert!(p.candidate_tiles("a", &ray).unwrap().is_empty());
+    }
+
+    #[test]
+    fn grid_walk_matches_all_tris() {
+        let world = world();
+        let t = TileId::from_num(0).children()[1];
+        // bumpy ground with a hole
+        let mut zs = (0..COUNT.pow(2))
+            .map(|i| {
+                let (x, y) = ((i % COUNT) as f32, (i / COUNT) as f32);
+                (50.0 + 20.0 * (x / 7.0).sin() * (y / 11.0).cos()) / 4064.0
+            })
+            .collect::<Vec<_>>();
+        zs[60 * COUNT + 60] = f32::NAN;
+        let sample = TileSample::from_zs(zs.clone()).unwrap();
+        let zr = sample.world_range(&world).unwrap();
+        let tris = tile_tris(&world, t, zs.clone());
+
+        let s = 4064.0;
+        let rays = [
+            down(500.0, 2100.0),
+            down(60.0 * 16.0, 2032.0 + 60.0 * 16.0),
+            vec![0.05, 0.1, 0.6, 0.08, -0.05, 0.1],
+            vec![0.4, 0.05, 0.9, -0.2, -0.01, -0.3],
+            vec![0.1, 0.015, 2100.0 / s, 1.0, 0.0, 0.0],
+        ];
+        let p = Picker::new(&world);
+        let mut hits = 0;
+        for r in rays {
+            let ray = p.ray(&r).unwrap();
+            let want = ray.nearest(tris.iter());
+            let got = ray.nearest_in_tile(&world, t, &zs, zr);
+            match (want, got) {
+                (Some(a), Some(b)) => {
+                    assert!((a - b).abs() < 1e-6, "{a} vs {b}");
+                    hits += 1;
+                }
+                (a, b) => assert_eq!(a, b),
+            }
+        }
+        assert!(hits >= 3, "{hits}");
+    }
+
+    #[test]
+    fn coarse_hit_outside_finer_tiles() {
+        let mut p = Picker::new(&world());
+        // the root at 30 m up, with only the south-west child loaded at 10 m up
+        let flat = |z: f64| vec![(z / 4064.0) as f32; COUNT.pow(2)];
+        let child = TileId::from_num(0).children()[0];
+        p.add_tile("a", 0, flat(30.0)).unwrap();
+        p.add_tile("a", child.as_num(), flat(10.0)).unwrap();
+
+        // a shallow ray west from over the south-east, which meets the root's ground there
+        // before reaching the child's (lower) ground
+        let s = 4064.0;
+        let ray = [3000.0 / s, 40.0 / s, 1000.0 / s, -1.0, -0.02, 0.0];
+        let h = p.pick(&ray).unwrap().unwrap();
+        assert_eq!(h.tile(), Some(0));
+        let Point3 { x, z, .. } = h.point();
+        assert!(
+            (x - 2500.0).abs() < 1e-3 && (z - 130.0).abs() < 1e-3,
+            "{x} {z}"
+        );
+
+        // straight down over the child, the finer tile wins
+        let h = p.pick(&down(1500.0, 1000.0)).unwrap().unwrap();
+        assert_eq!(h.tile(), Some(child.as_num()));
+    }
+}
@@ -0,0 +1,203 @@
+//! 2D polygon helpers for the surface analyses.
+//!
+//! Polygons are lists of XY points (no repeated closing point) and are anti-clockwise unless
+//! otherwise noted.
+use geom::*;
+
+/// Twice the signed area of a polygon, positive if anti-clockwise.
+pub fn area2(poly: &[Point2]) -> f64 {
+    let n = poly.len();
+    (0..n)
+        .map(|i| {
+            let ([x0, y0], [x1, y1]) = (poly[i], poly[(i + 1) % n]);
+            x0 * y1 - x1 * y0
+        })
+        .sum()
+}
+
+/// Reads a flat list of XY coordinates into an anti-clockwise polygon.
+///
+/// A closing point matching the first is dropped.
+pub fn from_flat(xys: &[f64]) -> Result<Vec<Point2>, String> {
+    if xys.len() & 1 != 0 {
+        return Err("polygon coordinates must be x,y pairs".to_string());
+    }
+
+    let mut poly = xys
+        .chunks_exact(2)
+        .map(|x| [x[0], x[1]])
+        .collect::<Vec<_>>();
+    if poly.len() > 1 && poly.first() == poly.last() {
+        poly.pop();
+    }
+    if poly.len() < 3 || poly.iter().flatten().any(|x| !x.is_finite()) {
+        return Err("polygon needs at least 3 finite points".to_string());
+    }
+    if area2(&poly) < 0.0 {
+        poly.reverse();
+    }
+
+    Ok(poly)
+}
+
+/// Triangulates a simple polygon by ear clipping, returning anti-clockwise triangles.
+pub fn triangulate(poly: &[Point2]) -> Vec<[Point2; 3]> {
+    let mut idx = (0..poly.len()).collect::<Vec<_>>();
+    if area2(poly) < 0.0 {
+        idx.reverse();
+    }
+
+    let mut tris = Vec::with_capacity(poly.len().saturating_sub(2));
+    while idx.len() > 3 {
+        let n = idx.len();
+        let ear = (0..n).find(|&i| {
+            let [a, b, c] = [idx[(i + n - 1) % n], idx[i], idx[(i + 1) % n]].map(|x| poly[x]);
+            cross(a, b, c) > 0.0
+                && idx
+                    .iter()
+                    .map(|x| poly[*x])
+                    .filter(|p| *p != a && *p != b && *p != c)
+                    .all(|p| !in_tri(p, [a, b, c]))
+        });
+
+        // a degenerate polygon has no ears, take what we have
+        let i = ear.unwrap_or(0);
+        let [a, b, c] = [idx[(i + n - 1) % n], idx[i], idx[(i + 1) % n]];
+        if ear.is_some() {
+            tris.push([a, b, c].map(|x| poly[x]));
+        }
+        idx.remove(i);
+    }
+
+    if idx.len() == 3 && cross(poly[idx[0]], poly[idx[1]], poly[idx[2]]) > 0.0 {
+        tris.push([idx[0], idx[1], idx[2]].map(|x| poly[x]));
+    }
+
+    tris
+}
+
+/// Clips the convex `subject` polygon to the convex, anti-clockwise `clip` polygon
+/// (Sutherland–Hodgman).
+pub fn clip_convex(subject: &[Point2], clip: &[Point2]) -> Vec<Point2> {
+    let mut out = subject.to_vec();
+    let n = clip.len();
+
+    for i in 0..n {
+        if out.is_empty() {
+            break;
+        }
+
+        let (a, b) = (clip[i], clip[(i + 1) % n]);
+        let input = std::mem::take(&mut out);
+        let m = input.len();
+        for j in 0..m {
+            let (p, q) = (input[j], input[(j + 1) % m]);
+            let (dp, dq) = (cross(a, b, p), cross(a, b, q));
+
+            if dp >= 0.0 {
+                out.push(p);
+            }
+            if (dp >= 0.0) != (dq >= 0.0) {
+                let t = dp / (dp - dq);
+                out.push(p.add(q.sub(p).scale(t)));
+            }
+        }
+    }
+
+    out
+}
+
+/// The axis-aligned square (anti-clockwise) of an extents.
+pub fn rect(extents: Extents2) -> [Point2; 4] {
+    let [x0, y0] = extents.origin;
+    let [x1, y1] = extents.max();
+    [[x0, y0], [x1, y0], [x1, y1], [x0, y1]]
+}
+
+/// Twice the signed area of the triangle abc.
+pub fn cross(a: Point2, b: Point2, c: Point2) -> f64 {
+    let ([ux, uy], [vx, vy]) = (b.sub(a), c.sub(a));
+    ux * vy - uy * vx
+}
+
+/// Whether `p` is inside (or on) the anti-clockwise triangle.
+pub fn in_tri(p: Point2, [a, b, c]: [Point2; 3]) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
+
+/// Interpolates the triangle's z at the XY point `p`, `None` if the triangle is vertical.
+pub fn z_at(tri: &Tri, p: Point2) -> Option<f64> {
+    let [a, b, c] = tri.map(|[x, y, _]| [x, y]);
+    let d = cross(a, b, c);
+    (d.abs() > f64::EPSILON).then(|| {
+        let (wa, wb) = (cross(b, c, p) / d, cross(c, a, p) / d);
+        wa * tri[0][2] + wb * tri[1][2] + (1.0 - wa - wb) * tri[2][2]
+    })
+}
+
+/// An optional clipping boundary, as convex (triangle) pieces.
+pub struct Boundary(Option<Vec<[Point2; 3]>>);
+
+impl Boundary {
+    pub fn new(xys: &[f64]) -> Result<Self, String> {
+        if xys.is_empty() {
+            Ok(Self(None))
+        } else {
+            from_flat(xys).map(|x| Self(Some(triangulate(&x))))
+        }
+    }
+
+    /// Whether there is a boundary to clip to.
+    pub fn is_some(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Clips a convex polygon to the boundary, returning the convex pieces inside.
+    pub fn clip(&self, p: &[Point2]) -> Vec<Vec<Point2>> {
+        match &self.0 {
+            None => vec![p.to_vec()],
+            Some(tris) => tris
+                .iter()
+                .map(|t| clip_convex(p, t))
+                .filter(|x| x.len() >= 3)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ear_clipping() {
+        // an L shape, listed clockwise
+        let poly = from_flat(&[
+            0.0, 0.0, 0.0, 2.0, 1.0, 2.0, 1.0, 1.0, 2.0, 1.0, 2.0, 0.0, 0.0, 0.0,
+        ])
+        .unwrap();
+        assert_eq!(poly.len(), 6);
+        assert_eq!(area2(&poly), 6.0);
+
+        let tris = triangulate(&poly);
+        assert_eq!(tris.len(), 4);
+        assert_eq!(tris.iter().map(|t| area2(t)).sum::<f64>(), 6.0);
+
+        assert!(from_flat(&[0.0, 0.0, 1.0]).is_err());
+        assert!(from_flat(&[0.0, 0.0, 1.0, 1.0]).is_err());
+    }
+
+    #[test]
+    fn clipping() {
+        let square = rect(Extents2 {
+            origin: [0.0, 0.0],
+            size: [2.0, 2.0],
+        });
+        let tri = [[1.0, 1.0], [3.0, 1.0], [1.0, 3.0]];
+        let x = clip_convex(&tri, &square);
+        assert_eq!(area2(&x), 2.0);
+
+        let outside = [[5.0, 5.0], [6.0, 5.0], [5.0, 6.0]];
+        assert_eq!(area2(&clip_convex(&outside, &square)), 0.0);
+    }
+}
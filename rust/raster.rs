@@ -0,0 +1,195 @@
+//! Terrain analysis rasters (slope, aspect, hillshade) over tile grids.
+use super::*;
+
+/// The padded grid stride, a ring of neighbouring samples surrounds the tile.
+const PAD: usize = COUNT + 2;
+
+/// A tile's z grid prepared for terrain analysis.
+///
+/// The gradients are worked out with Horn's method (a 3x3 window) at the tile's ground spacing
+/// (`lod_res`). The window needs the samples just outside the tile, these can be supplied
+/// from the neighbouring tiles (at the same LOD) with [`Self::set_neighbour`]. Where a sample
+/// is missing, the centre sample is used in its place.
+///
+/// The output rasters are the same `COUNT`² shape (and x order) as the tile zs, with `NaN`s
+/// wherever the tile has no data.
+#[wasm_bindgen]
+pub struct TerrainGrid {
+    /// Heights in world units (metres), padded.
+    zs: Vec<f64>,
+    spacing: f64,
+}
+
+#[wasm_bindgen]
+impl TerrainGrid {
+    /// Prepare a tile's `zs` (in render space, as stored) for analysis.
+    pub fn new(extents: &Extents3, tile_idx: u32, zs: &[f32]) -> Result<TerrainGrid, String> {
+        check_len(zs)?;
+
+        let scaler = extents.max_dim();
+        let mut grid = vec![f64::NAN; PAD * PAD];
+        for (i, z) in zs.iter().enumerate() {
+            let (x, y) = (i % COUNT, i / COUNT);
+            grid[(y + 1) * PAD + x + 1] = *z as f64 * scaler;
+        }
+
+        Ok(Self {
+            zs: grid,
+            spacing: TileId::from_num(tile_idx).lod_res(),
+        })
+    }
+
+    /// Supply the `zs` of a neighbouring tile at the same LOD.
+    ///
+    /// Sides are ordered west (-x), east (+x), south (-y), north (+y), as in
+    /// [`ViewableTiles::neighbour_lods`]. Tiles share their edge samples, so the samples one
+    /// in from the neighbour's edge are used.
+    pub fn set_neighbour(
+        &mut self,
+        side: u8,
+        extents: &Extents3,
+        zs: &[f32],
+    ) -> Result<(), String> {
+        check_len(zs)?;
+
+        if side > 3 {
+            return Err(format!("side must be 0-3, found {side}"));
+        }
+
+        let scaler = extents.max_dim();
+        let last = COUNT - 1;
+        for i in 0..COUNT {
+            // (neighbour index, padded index)
+            let (n, p) = match side {
+                0 => (i * COUNT + last - 1, (i + 1) * PAD),
+                1 => (i * COUNT + 1, (i + 1) * PAD + PAD - 1),
+                2 => ((last - 1) * COUNT + i, i + 1),
+                _ => (COUNT + i, (PAD - 1) * PAD + i + 1),
+            };
+            self.zs[p] = zs[n] as f64 * scaler;
+        }
+
+        Ok(())
+    }
+
+    /// The slope in degrees from horizontal.
+    pub fn slope(&self) -> Vec<f32> {
+        self.map_gradient(|[dx, dy]| dx.hypot(dy).atan().to_degrees())
+    }
+
+    /// The aspect (the downhill direction) in degrees clockwise from north.
+    ///
+    /// Flat ground has no aspect and is `NaN`.
+    pub fn aspect(&self) -> Vec<f32> {
+        self.map_gradient(|[dx, dy]| {
+            if dx == 0.0 && dy == 0.0 {
+                f64::NAN
+            } else {
+                (-dx).atan2(-dy).to_degrees().rem_euclid(360.0)
+            }
+        })
+    }
+
+    /// Shaded relief (0-1) lit by a sun at `azimuth` (degrees clockwise from north) and
+    /// `altitude` (degrees above the horizon).
+    pub fn hillshade(&self, azimuth: f64, altitude: f64) -> Vec<f32> {
+        let (a, h) = (azimuth.to_radians(), altitude.to_radians());
+        let sun = [a.sin() * h.cos(), a.cos() * h.cos(), h.sin()];
+
+        self.map_gradient(|[dx, dy]| {
+            let n = [-dx, -dy, 1.0].unit();
+            (n[0] * sun[0] + n[1] * sun[1] + n[2] * sun[2]).max(0.0)
+        })
+    }
+}
+
+impl TerrainGrid {
+    /// Horn's gradient (dz/dx, dz/dy) at each tile sample, mapped through `f`.
+    fn map_gradient<F>(&self, f: F) -> Vec<f32>
+    where
+        F: Fn([f64; 2]) -> f64,
+    {
+        let z = |x: usize, y: usize| self.zs[y * PAD + x];
+        let d = self.spacing * 8.0;
+
+        let mut out = Vec::with_capacity(COUNT.pow(2));
+        for y in 1..=COUNT {
+            for x in 1..=COUNT {
+                let e = z(x, y);
+                if !e.is_finite() {
+                    out.push(f32::NAN);
+                    continue;
+                }
+
+                // missing samples take the centre
+                let s = |x, y| Some(z(x, y)).filter(|z| z.is_finite()).unwrap_or(e);
+                let [a, b, c] = [s(x - 1, y + 1), s(x, y + 1), s(x + 1, y + 1)];
+                let [dd, ff] = [s(x - 1, y), s(x + 1, y)];
+                let [g, h, i] = [s(x - 1, y - 1), s(x, y - 1), s(x + 1, y - 1)];
+
+                let dx = ((c + 2.0 * ff + i) - (a + 2.0 * dd + g)) / d;
+                let dy = ((a + 2.0 * b + c) - (g + 2.0 * h + i)) / d;
+                out.push(f([dx, dy]) as f32);
+            }
+        }
+
+        out
+    }
+}
+
+fn check_len(zs: &[f32]) -> Result<(), String> {
+    if zs.len() == COUNT.pow(2) {
+        Ok(())
+    } else {
+        Err(format!(
+            "expecting {} tile zs, found {}",
+            COUNT.pow(2),
+            zs.len()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> Extents3 {
+        Extents3 {
+            origin: Point3::zero(),
+            size: [4064.0, 4064.0, 100.0].into(),
+        }
+    }
+
+    /// A plane falling 1:1 to the east, in render space.
+    fn plane(offset: f64) -> Vec<f32> {
+        (0..COUNT.pow(2))
+            .map(|i| ((offset - (i % COUNT) as f64 * 32.0) / 4064.0) as f32)
+            .collect()
+    }
+
+    #[test]
+    fn plane_rasters() {
+        let grid = TerrainGrid::new(&world(), 0, &plane(5000.0)).unwrap();
+
+        let i = 64 * COUNT + 64;
+        assert!((grid.slope()[i] - 45.0).abs() < 1e-3);
+        assert!((grid.aspect()[i] - 90.0).abs() < 1e-3);
+        assert!((grid.hillshade(90.0, 45.0)[i] - 1.0).abs() < 1e-3);
+        assert!(grid.hillshade(270.0, 45.0)[i].abs() < 1e-3);
+    }
+
+    #[test]
+    fn neighbours_fix_edges() {
+        let mut grid = TerrainGrid::new(&world(), 0, &plane(5000.0)).unwrap();
+        let edge = 64 * COUNT + COUNT - 1;
+        assert!(grid.slope()[edge] < 40.0);
+
+        // the east neighbour continues the plane from the shared edge
+        grid.set_neighbour(1, &world(), &plane(5000.0 - 127.0 * 32.0))
+            .unwrap();
+        assert!((grid.slope()[edge] - 45.0).abs() < 1e-3);
+
+        assert!(grid.set_neighbour(4, &world(), &plane(0.0)).is_err());
+        assert!(TerrainGrid::new(&world(), 0, &[0.0]).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! Right-triangulated irregular network (RTIN) meshing of tile grids.
+//!
+//! This follows the approach of [mapbox/martini](https://github.com/mapbox/martini): the grid is
+//! recursively split into right triangles, and the split is only taken if the error of the
+//! coarser triangle is over the tolerance.
+//! RTIN requires a `2^k + 1` grid, so the `COUNT`² tile grid is padded out to `GRID`².
+//! Padded and `NaN` nodes are _invalid_; any triangle touching an invalid node is refined
+//! down to the leaves, and leaves with an invalid node are dropped.
+//! This gives the same extents as the smooth meshing, but the holes are larger: the leaf
+//! diagonals alternate, so a `NaN` node loses all 8 triangles around it (rather than 6).
+//! The tile edges are always fully refined, so neighbouring tiles share every edge vertex and
+//! meet without cracks or T-junctions.
+use super::COUNT;
+
+/// The padded grid stride.
+const GRID: usize = COUNT + 1;
+const MAX: usize = GRID - 1;
+
+/// Triangulates the tile grid `zs` (`COUNT`² in x order) to within `max_error` (same units as
+/// `zs`).
+///
+/// Returns triangles as `COUNT`² grid indices. Triangles wind the same as the smooth
+/// triangulation (anti-clockwise looking down).
+pub fn triangulate(zs: &[f32], max_error: f32) -> Vec<[usize; 3]> {
+    assert_eq!(zs.len(), COUNT.pow(2));
+
+    let terrain = (0..GRID * GRID)
+        .map(|i| {
+            let (x, y) = (i % GRID, i / GRID);
+            if x < COUNT && y < COUNT {
+                zs[y * COUNT + x]
+            } else {
+                f32::NAN
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let errors = errors(&terrain);
+
+    let mut tris = Vec::new();
+    let mut f = |[ax, ay]: [usize; 2], [bx, by]: [usize; 2], [cx, cy]: [usize; 2]| {
+        // flip to anti-clockwise and map back to the unpadded grid
+        tris.push([[ax, ay], [cx, cy], [bx, by]].map(|[x, y]| y * COUNT + x));
+    };
+    let valid = |[x, y]: [usize; 2]| terrain[y * GRID + x].is_finite();
+
+    split(
+        &errors,
+        max_error,
+        &valid,
+        &mut f,
+        [0, 0],
+        [MAX, MAX],
+        [MAX, 0],
+    );
+    split(
+        &errors,
+        max_error,
+        &valid,
+        &mut f,
+        [MAX, MAX],
+        [0, 0],
+        [0, MAX],
+    );
+
+    tris
+}
+
+/// Computes the error at each hypotenuse midpoint, propagated up through the parents.
+fn errors(terrain: &[f32]) -> Vec<f32> {
+    let tile = MAX;
+    let num_tris = tile * tile * 2 - 2;
+    let num_parents = num_tris - tile * tile;
+
+    let mut errors = vec![0f32; GRID * GRID];
+    let z = |x: usize, y: usize| terrain[y * GRID + x];
+
+    // work from the smallest triangles up, so the children are done before the parents
+    for i in (0..num_tris).rev() {
+        let [ax, ay, bx, by] = coords(i);
+        let (mx, my) = ((ax + bx) >> 1, (ay + by) >> 1);
+        let (cx, cy) = (mx + my - ay, my + ax - mx);
+        let mid = my * GRID + mx;
+
+        let (za, zb, zm, zc) = (z(ax, ay), z(bx, by), z(mx, my), z(cx, cy));
+        // the west and south edges (the east and north edges border the padding)
+        let edge = mx == 0 || my == 0;
+        let e = if !edge && [za, zb, zm, zc].iter().all(|x| x.is_finite()) {
+            ((za + zb) * 0.5 - zm).abs()
+        } else {
+            // always refine along the tile edges and around invalid nodes
+            f32::INFINITY
+        };
+
+        let mut e = errors[mid].max(e);
+        if i < num_parents {
+            let left = ((ay + cy) >> 1) * GRID + ((ax + cx) >> 1);
+            let right = ((by + cy) >> 1) * GRID + ((bx + cx) >> 1);
+            e = e.max(errors[left]).max(errors[right]);
+        }
+
+        errors[mid] = e;
+    }
+
+    errors
+}
+
+/// The hypotenuse (a, b) coordinates of the `i`th triangle in the implicit binary tree.
+fn coords(i: usize) -> [usize; 4] {
+    let mut id = i + 2;
+    let (mut ax, mut ay, mut bx, mut by, mut cx, mut cy) = (0, 0, 0, 0, 0, 0);
+    if id & 1 == 1 {
+        // bottom-left triangle
+        bx = MAX;
+        by = MAX;
+        cx = MAX;
+    } else {
+        // top-right triangle
+        ax = MAX;
+        ay = MAX;
+        cy = MAX;
+    }
+
+    loop {
+        id >>= 1;
+        if id <= 1 {
+            break;
+        }
+
+        let (mx, my) = ((ax + bx) >> 1, (ay + by) >> 1);
+        if id & 1 == 1 {
+            // left half
+            (bx, by, ax, ay) = (ax, ay, cx, cy);
+        } else {
+            // right half
+            (ax, ay, bx, by) = (bx, by, cx, cy);
+        }
+        (cx, cy) = (mx, my);
+    }
+
+    [ax, ay, bx, by]
+}
+
+fn split<V, F>(
+    errors: &[f32],
+    max_error: f32,
+    valid: &V,
+    emit: &mut F,
+    a: [usize; 2],
+    b: [usize; 2],
+    c: [usize; 2],
+) where
+    V: Fn([usize; 2]) -> bool,
+    F: FnMut([usize; 2], [usize; 2], [usize; 2]),
+{
+    let m = [(a[0] + b[0]) >> 1, (a[1] + b[1]) >> 1];
+    let leaf = a[0].abs_diff(c[0]) + a[1].abs_diff(c[1]) <= 1;
+
+    if !leaf && errors[m[1] * GRID + m[0]] > max_error {
+        split(errors, max_error, valid, emit, c, a, m);
+        split(errors, max_error, valid, emit, b, c, m);
+    } else if valid(a) && valid(b) && valid(c) {
+        emit(a, b, c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Twice the signed area, positive is anti-clockwise.
+    fn orient(t: [usize; 3]) -> isize {
+        let [a, b, c] = t.map(|i| [(i % COUNT) as isize, (i / COUNT) as isize]);
+        (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+    }
+
+    #[test]
+    fn flat_grid_is_sparse() {
+        let zs = vec![1.0; COUNT.pow(2)];
+        let tris = triangulate(&zs, 0.0);
+
+        // only the strips along the tile edges get fully refined
+        assert!(tris.len() < 2_000, "{}", tris.len());
+        assert!(tris.iter().all(|t| orient(*t) > 0));
+
+        // the triangles cover the whole tile
+        let area: isize = tris.iter().map(|t| orient(*t)).sum();
+        assert_eq!(area, 2 * (COUNT as isize - 1).pow(2));
+    }
+
+    #[test]
+    fn nans_are_holes() {
+        let mut zs = vec![1.0; COUNT.pow(2)];
+        zs[40 * COUNT + 40] = f32::NAN;
+        let tris = triangulate(&zs, 0.0);
+
+        assert!(tris.iter().all(|t| !t.contains(&(40 * COUNT + 40))));
+        // the diagonals meet at even nodes, so the 8 triangles around it are lost
+        let area: isize = tris.iter().map(|t| orient(*t)).sum();
+        assert_eq!(area, 2 * (COUNT as isize - 1).pow(2) - 8);
+    }
+
+    #[test]
+    fn neighbours_share_edge_vertices() {
+        // two tiles side by side, sharing the column of nodes between them
+        let f = |x: usize, y: usize| ((x as f32 / 9.0).sin() + (y as f32 / 13.0).cos()) * 0.1;
+        let last = COUNT - 1;
+        let west = (0..COUNT.pow(2))
+            .map(|i| f(i % COUNT, i / COUNT))
+            .collect::<Vec<_>>();
+        let east = (0..COUNT.pow(2))
+            .map(|i| f(i % COUNT + last, i / COUNT))
+            .collect::<Vec<_>>();
+
+        let edge = |tris: Vec<[usize; 3]>, x: usize| {
+            let mut ys = tris
+                .into_iter()
+                .flatten()
+                .filter(|i| i % COUNT == x)
+                .map(|i| i / COUNT)
+                .collect::<Vec<_>>();
+            ys.sort_unstable();
+            ys.dedup();
+            ys
+        };
+        let a = edge(triangulate(&west, 0.01), last);
+        let b = edge(triangulate(&east, 0.01), 0);
+        assert_eq!(a, b);
+        assert_eq!(a.len(), COUNT);
+    }
+
+    #[test]
+    fn tolerance_reduces_tris() {
+        let zs = (0..COUNT.pow(2))
+            .map(|i| ((i % COUNT) as f32 / 10.0).sin())
+            .collect::<Vec<_>>();
+
+        let fine = triangulate(&zs, 0.0).len();
+        let coarse = triangulate(&zs, 0.05).len();
+        assert!(coarse < fine / 2, "{coarse} vs {fine}");
+    }
+}
@@ -0,0 +1,341 @@
+//! Section (profile) cuts along a polyline.
+use super::*;
+use poly::{cross, z_at};
+use volume::TriGrid;
+
+/// A surface's profile along a section polyline.
+///
+/// The profile is a list of (chainage, z) points, where chainage is the distance along the
+/// polyline from its start. Gaps where the surface is missing are marked with a `NaN` z; a gap
+/// runs from the marker's chainage to the next point's, so a marker may share its chainage with
+/// the surface edge before it.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Section {
+    chainages: Vec<f64>,
+    zs: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl TriangleMeshSurface {
+    /// The exact profile of the mesh along the `polyline` (flat world XY coordinates).
+    ///
+    /// A point is returned wherever the polyline crosses a triangle edge.
+    pub fn section(&self, polyline: &[f64]) -> Result<Section, String> {
+        init_panic_hook();
+
+        let line = polyline_from_flat(polyline)?;
+        let grid = TriGrid::new(self.tris().collect());
+
+        let mut s = Section::default();
+        let mut ch = 0.0;
+        for leg in line.windows(2) {
+            let (a, b) = (leg[0], leg[1]);
+            let len = b.sub(a).mag();
+
+            // the (t0, z0, t1, z1) span of each triangle the leg crosses
+            let mut spans = grid
+                .query(Extents2::from_iter([a, b]))
+                .filter_map(|tri| cross_tri(tri, a, b))
+                .collect::<Vec<_>>();
+            spans.sort_unstable_by(|x, y| x.0.total_cmp(&y.0));
+
+            let mut end = 0.0;
+            for (t0, z0, t1, z1) in spans {
+                if t0 > end + 1e-9 {
+                    s.push(ch + end * len, f64::NAN);
+                }
+                s.push(ch + t0 * len, z0);
+                s.push(ch + t1 * len, z1);
+                end = t1.max(end);
+            }
+            if end < 1.0 - 1e-9 {
+                s.push(ch + end * len, f64::NAN);
+            }
+
+            ch += len;
+        }
+
+        Ok(s)
+    }
+}
+
+#[wasm_bindgen]
+impl Section {
+    pub fn len(&self) -> usize {
+        self.zs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zs.is_empty()
+    }
+
+    /// The distance along the polyline of each point.
+    pub fn chainages(&self) -> Vec<f64> {
+        self.chainages.clone()
+    }
+
+    /// The world elevation of each point, `NaN` marks a gap.
+    pub fn zs(&self) -> Vec<f64> {
+        self.zs.clone()
+    }
+}
+
+impl Section {
+    /// Pushes a point, skipping repeats and consecutive gaps.
+    ///
+    /// A gap marker is kept even at the chainage of the point before it, since it breaks the
+    /// profile there.
+    fn push(&mut self, ch: f64, z: f64) {
+        if let (Some(c), Some(p)) = (self.chainages.last(), self.zs.last()) {
+            let same = (c - ch).abs() < 1e-9;
+            if p.is_nan() && z.is_nan() || same && (p - z).abs() < 1e-9 {
+                return;
+            }
+        }
+
+        self.chainages.push(ch);
+        self.zs.push(z);
+    }
+}
+
+/// Samples tile grids at a LOD along a section polyline, as a faster approximate profile.
+///
+/// Ask for the [`Self::tiles`] the polyline passes through, supply each tile's zs with
+/// [`Self::add_tile`], then get the [`Self::section`]. The polyline is sampled at the LOD's
+/// resolution and the grids are interpolated bilinearly.
+#[wasm_bindgen]
+pub struct SectionSampler {
+    extents: Extents3,
+    chainages: Vec<f64>,
+    zs: Vec<f64>,
+    /// tile -> sample (index, point)
+    tiles: HashMap<u32, Vec<(usize, Point2)>>,
+}
+
+#[wasm_bindgen]
+impl SectionSampler {
+    pub fn new(
+        extents: &Extents3,
+        polyline: &[f64],
+        lod_lvl: u8,
+    ) -> Result<SectionSampler, String> {
+        init_panic_hook();
+
+        if lod_lvl > MAX_DEPTH {
+            return Err(format!("LOD level must be 0-{MAX_DEPTH}, found {lod_lvl}"));
+        }
+        let line = polyline_from_flat(polyline)?;
+        let spacing = LODS[lod_lvl as usize];
+
+        let mut this = Self {
+            extents: *extents,
+            chainages: Vec::new(),
+            zs: Vec::new(),
+            tiles: HashMap::default(),
+        };
+
+        let mut ch = 0.0;
+        for (i, leg) in line.windows(2).enumerate() {
+            let (a, b) = (leg[0], leg[1]);
+            let len = b.sub(a).mag();
+            let n = (len / spacing).ceil().max(1.0) as usize;
+            // legs share their end points
+            let start = usize::from(i > 0);
+            for j in start..=n {
+                let t = j as f64 / n as f64;
+                let p = a.add(b.sub(a).scale(t));
+                if let Some(tile) = TileId::at(extents, p, lod_lvl) {
+                    let idx = this.zs.len();
+                    this.tiles.entry(tile.as_num()).or_default().push((idx, p));
+                }
+                this.chainages.push(ch + t * len);
+                this.zs.push(f64::NAN);
+            }
+            ch += len;
+        }
+
+        Ok(this)
+    }
+
+    /// The tiles the polyline passes through (sorted).
+    pub fn tiles(&self) -> Vec<u32> {
+        let mut x = self.tiles.keys().copied().collect::<Vec<_>>();
+        x.sort_unstable();
+        x
+    }
+
+    /// Interpolate the samples which fall in this tile from its `zs` (stored, render space).
+    pub fn add_tile(&mut self, tile_idx: u32, zs: &[f32]) -> Result<(), String> {
+        if zs.len() != COUNT.pow(2) {
+            return Err(format!("expecting {} tile zs", COUNT.pow(2)));
+        }
+
+        let Some(samples) = self.tiles.get(&tile_idx) else { return Ok(()); };
+
+        let tile = TileId::from_num(tile_idx);
+        let origin = tile.extents(&self.extents).origin;
+        let res = tile.lod_res();
+        let scaler = self.extents.max_dim();
+        let z0 = self.extents.origin.z;
+        let z = |x: usize, y: usize| zs[y * COUNT + x] as f64 * scaler + z0;
+
+        for (idx, p) in samples {
+            let [fx, fy] = p.sub(origin).scale(res.recip());
+            let last = (COUNT - 2) as f64;
+            let (x, y) = (fx.floor().clamp(0.0, last), fy.floor().clamp(0.0, last));
+            let (u, v) = (fx - x, fy - y);
+            let (x, y) = (x as usize, y as usize);
+
+            let bottom = z(x, y) * (1.0 - u) + z(x + 1, y) * u;
+            let top = z(x, y + 1) * (1.0 - u) + z(x + 1, y + 1) * u;
+            self.zs[*idx] = bottom * (1.0 - v) + top * v;
+        }
+
+        Ok(())
+    }
+
+    /// The sampled profile so far, samples in tiles not (yet) added are gaps.
+    pub fn section(&self) -> Section {
+        let mut s = Section::default();
+        for (c, z) in self.chainages.iter().zip(&self.zs) {
+            s.push(*c, *z);
+        }
+        s
+    }
+}
+
+/// Reads a flat list of XY coordinates into a polyline of at least 2 points.
+fn polyline_from_flat(xys: &[f64]) -> Result<Vec<Point2>, String> {
+    if xys.len() & 1 != 0 {
+        return Err("polyline coordinates must be x,y pairs".to_string());
+    }
+    if xys.len() < 4 || xys.iter().any(|x| !x.is_finite()) {
+        return Err("polyline needs at least 2 finite points".to_string());
+    }
+
+    Ok(xys.chunks_exact(2).map(|x| [x[0], x[1]]).collect())
+}
+
+/// The span of the segment `a-b` over the triangle, as `(t0, z0, t1, z1)`.
+fn cross_tri(tri: &Tri, a: Point2, b: Point2) -> Option<(f64, f64, f64, f64)> {
+    let mut xy = tri.map(|[x, y, _]| [x, y]);
+    let area = cross(xy[0], xy[1], xy[2]);
+    if area.abs() <= f64::EPSILON {
+        return None;
+    }
+    if area < 0.0 {
+        xy.swap(1, 2);
+    }
+
+    // clip the segment's parameter range by each edge's half plane
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    for i in 0..3 {
+        let (u, v) = (xy[i], xy[(i + 1) % 3]);
+        let (fa, fb) = (cross(u, v, a), cross(u, v, b));
+        if fa < 0.0 && fb < 0.0 {
+            return None;
+        } else if fa < 0.0 {
+            t0 = t0.max(fa / (fa - fb));
+        } else if fb < 0.0 {
+            t1 = t1.min(fa / (fa - fb));
+        }
+    }
+
+    let at = |t: f64| z_at(tri, a.add(b.sub(a).scale(t)));
+    (t1 - t0 > 1e-9).then(|| Some((t0, at(t0)?, t1, at(t1)?)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> Extents3 {
+        Extents3 {
+            origin: [0.0, 0.0, 100.0].into(),
+            size: [4064.0, 4064.0, 100.0].into(),
+        }
+    }
+
+    #[test]
+    fn mesh_section() {
+        // a plane rising 1:10 to the east over 0-100
+        let m = TriangleMeshSurface {
+            translate: [0.0, 0.0, 100.0].into(),
+            points: vec![0., 0., 0., 100., 0., 10., 100., 100., 10., 0., 100., 0.],
+            indices: vec![0, 1, 2, 2, 3, 0],
+        };
+
+        // starts off the mesh, crosses the diagonal, then turns north
+        let s = m.section(&[-10.0, 20.0, 50.0, 20.0, 50.0, 50.0]).unwrap();
+        let (cs, zs) = (s.chainages(), s.zs());
+        assert_eq!(cs[0], 0.0);
+        assert!(zs[0].is_nan());
+        assert_eq!(cs[1], 10.0);
+        assert!((zs[1] - 100.0).abs() < 1e-9);
+        // the diagonal is crossed at x = 20
+        assert!((cs[2] - 30.0).abs() < 1e-9 && (zs[2] - 102.0).abs() < 1e-9);
+        assert!((cs[3] - 60.0).abs() < 1e-9 && (zs[3] - 105.0).abs() < 1e-9);
+        assert!((cs.last().unwrap() - 90.0).abs() < 1e-9);
+        assert!((zs.last().unwrap() - 105.0).abs() < 1e-9);
+        assert!(zs[1..].iter().all(|z| z.is_finite()));
+
+        assert!(m.section(&[0.0, 0.0]).is_err());
+        assert!(m.section(&[0.0, 0.0, 1.0]).is_err());
+    }
+
+    #[test]
+    fn mesh_section_gaps() {
+        // two flat squares, over x 0-40 and 60-100
+        let m = TriangleMeshSurface {
+            translate: [0.0, 0.0, 100.0].into(),
+            points: vec![
+                0., 0., 0., 40., 0., 0., 40., 100., 0., 0., 100., 0., //
+                60., 0., 0., 100., 0., 0., 100., 100., 0., 60., 100., 0.,
+            ],
+            indices: vec![0, 1, 2, 2, 3, 0, 4, 5, 6, 6, 7, 4],
+        };
+
+        // crosses the hole between the squares and runs off the end
+        let s = m.section(&[10.0, 20.0, 120.0, 20.0]).unwrap();
+        let (cs, zs) = (s.chainages(), s.zs());
+        let gaps = cs
+            .iter()
+            .zip(&zs)
+            .filter(|(_, z)| z.is_nan())
+            .map(|(c, _)| *c)
+            .collect::<Vec<_>>();
+        assert_eq!(gaps.len(), 2);
+        // the interior hole starts at the first square's edge
+        assert!((gaps[0] - 30.0).abs() < 1e-9);
+        let i = zs.iter().position(|z| z.is_nan()).unwrap();
+        assert!((cs[i - 1] - 30.0).abs() < 1e-9 && (zs[i - 1] - 100.0).abs() < 1e-9);
+        assert!((cs[i + 1] - 50.0).abs() < 1e-9 && (zs[i + 1] - 100.0).abs() < 1e-9);
+        // the trailing gap is kept past the second square
+        assert!((gaps[1] - 90.0).abs() < 1e-9);
+        assert!(zs.last().unwrap().is_nan());
+    }
+
+    #[test]
+    fn tile_section() {
+        // a plane rising 1:10 to the east, in render space
+        let zs = (0..COUNT.pow(2))
+            .map(|i| ((i % COUNT) as f64 * 3.2 / 4064.0) as f32)
+            .collect::<Vec<_>>();
+
+        let mut s = SectionSampler::new(&world(), &[100.0, 10.0, 300.0, 10.0], 0).unwrap();
+        assert_eq!(s.tiles(), vec![0]);
+        assert!(s.section().zs().iter().all(|z| z.is_nan()));
+
+        s.add_tile(0, &zs).unwrap();
+        let sec = s.section();
+        assert_eq!(sec.len(), 8);
+        assert!(sec
+            .chainages()
+            .iter()
+            .zip(sec.zs())
+            .all(|(c, z)| (z - (110.0 + c * 0.1)).abs() < 1e-3));
+
+        assert!(SectionSampler::new(&world(), &[0.0, 0.0, 1.0, 1.0], 9).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+//! Cut/fill volumes between two surfaces.
+use super::*;
+use poly::{clip_convex, cross, rect, z_at, Boundary};
+
+/// Cut and fill volumes of surface _A_ relative to surface _B_, broken down by tile.
+///
+/// _Fill_ is where A is above B, _cut_ is where A is below B, and the _net_ is fill less cut.
+/// For example, with A as the as-built and B as the design, fill is material left above the
+/// design. Volumes are in world units (cubic metres).
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct VolumeReport {
+    /// tile -> (cut, fill)
+    tiles: HashMap<u32, (f64, f64)>,
+}
+
+#[wasm_bindgen]
+impl VolumeReport {
+    /// Volumes between matching tiles of two objects at the same LOD.
+    ///
+    /// `a_zs` and `b_zs` are the stored (render space) tile zs. The volume is integrated
+    /// exactly over the tile's grid triangulation wherever both surfaces have data.
+    /// `boundary` is an optional clipping polygon as flat world XY coordinates (pass an empty
+    /// array for none).
+    pub fn from_tiles(
+        extents: &Extents3,
+        tile_idx: u32,
+        a_zs: &[f32],
+        b_zs: &[f32],
+        boundary: &[f64],
+    ) -> Result<VolumeReport, String> {
+        init_panic_hook();
+
+        if a_zs.len() != COUNT.pow(2) || b_zs.len() != COUNT.pow(2) {
+            return Err(format!("expecting {} tile zs", COUNT.pow(2)));
+        }
+        let boundary = Boundary::new(boundary)?;
+
+        // grid of the differences, NaN where either is missing
+        let dz = a_zs.iter().zip(b_zs).map(|(a, b)| a - b).collect();
+        let z0 = extents.origin.z;
+
+        let mut this = Self::default();
+        for tri in tile_tris(extents, TileId::from_num(tile_idx), dz) {
+            let tri = tri.map(|[x, y, z]| [x, y, z - z0]);
+            let xy = tri.map(|[x, y, _]| [x, y]);
+            for poly in boundary.clip(&xy) {
+                let d = poly
+                    .iter()
+                    .map(|p| z_at(&tri, *p).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                this.add(tile_idx, &poly, &d);
+            }
+        }
+
+        // always report the tile, even if it is empty
+        this.tiles.entry(tile_idx).or_default();
+
+        Ok(this)
+    }
+
+    /// Volumes between two triangle meshes, using exact prism integration.
+    ///
+    /// The triangles of both meshes are overlaid so the difference is integrated exactly.
+    /// The volumes are broken down into the tiles at `lod_lvl` of the data `extents`.
+    /// `boundary` is an optional clipping polygon as flat world XY coordinates (pass an empty
+    /// array for none).
+    pub fn between_meshes(
+        a: &TriangleMeshSurface,
+        b: &TriangleMeshSurface,
+        extents: &Extents3,
+        lod_lvl: u8,
+        boundary: &[f64],
+    ) -> Result<VolumeReport, String> {
+        init_panic_hook();
+
+        if lod_lvl > MAX_DEPTH {
+            return Err(format!("LOD level must be 0-{MAX_DEPTH}, found {lod_lvl}"));
+        }
+        let boundary = Boundary::new(boundary)?;
+
+        let grid = TriGrid::new(b.tris().collect());
+        let ts = tile_size(lod_lvl as usize);
+        let origin = Extents2::from(*extents).origin;
+
+        let mut this = Self::default();
+        for ta in a.tris() {
+            let a_xy = ccw(ta.map(|[x, y, _]| [x, y]));
+            for tb in grid.query(Extents2::from(ta.aabb())) {
+                let b_xy = ccw(tb.map(|[x, y, _]| [x, y]));
+                let piece = clip_convex(&a_xy, &b_xy);
+                if piece.len() < 3 {
+                    continue;
+                }
+
+                for poly in boundary.clip(&piece) {
+                    // break down into the tiles the piece covers
+                    let e = Extents2::from_iter(poly.iter().map(|p| p.sub(origin)));
+                    let [x0, y0] = e.origin.scale(ts.recip()).map(f64::floor);
+                    let [x1, y1] = e.max().scale(ts.recip()).map(f64::ceil);
+                    for gx in x0 as i64..x1 as i64 {
+                        for gy in y0 as i64..y1 as i64 {
+                            let c = [gx as f64 + 0.5, gy as f64 + 0.5].scale(ts).add(origin);
+                            let Some(tile) = TileId::at(extents, c, lod_lvl) else { continue; };
+                            let poly = clip_convex(&poly, &rect(tile.extents(extents)));
+                            let d = poly
+                                .iter()
+                                .map(|p| {
+                                    let za = z_at(&ta, *p).unwrap_or_default();
+                                    let zb = z_at(tb, *p).unwrap_or_default();
+                                    za - zb
+                                })
+                                .collect::<Vec<_>>();
+                            this.add(tile.as_num(), &poly, &d);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(this)
+    }
+
+    /// Adds another report's volumes into this one (eg accumulating tiles).
+    pub fn merge(&mut self, other: &VolumeReport) {
+        for (t, (c, f)) in &other.tiles {
+            let x = self.tiles.entry(*t).or_default();
+            x.0 += c;
+            x.1 += f;
+        }
+    }
+
+    /// The total volume where A is below B.
+    pub fn cut(&self) -> f64 {
+        self.tiles.values().map(|x| x.0).sum()
+    }
+
+    /// The total volume where A is above B.
+    pub fn fill(&self) -> f64 {
+        self.tiles.values().map(|x| x.1).sum()
+    }
+
+    /// Fill less cut.
+    pub fn net(&self) -> f64 {
+        self.fill() - self.cut()
+    }
+
+    /// The tiles in the report (sorted), the per tile volumes follow this order.
+    pub fn tiles(&self) -> Vec<u32> {
+        let mut x = self.tiles.keys().copied().collect::<Vec<_>>();
+        x.sort_unstable();
+        x
+    }
+
+    pub fn tile_cuts(&self) -> Vec<f64> {
+        self.tiles().into_iter().map(|t| self.tiles[&t].0).collect()
+    }
+
+    pub fn tile_fills(&self) -> Vec<f64> {
+        self.tiles().into_iter().map(|t| self.tiles[&t].1).collect()
+    }
+}
+
+impl VolumeReport {
+    /// Integrates the linear difference `d` (at each vertex) over the convex polygon.
+    fn add(&mut self, tile: u32, poly: &[Point2], d: &[f64]) {
+        let x = self.tiles.entry(tile).or_default();
+        for i in 1..poly.len().saturating_sub(1) {
+            let area = cross(poly[0], poly[i], poly[i + 1]).abs() * 0.5;
+            let (pos, neg) = split_prism(area, [d[0], d[i], d[i + 1]]);
+            x.0 += neg;
+            x.1 += pos;
+        }
+    }
+}
+
+/// The positive and negative volumes of a triangular prism with linear heights `d` over a base
+/// `area`.
+fn split_prism(area: f64, d: [f64; 3]) -> (f64, f64) {
+    // the volume above zero when only `p` is above
+    let one = |p: f64, q: f64, r: f64| area * p.powi(3) / (3.0 * (p - q) * (p - r));
+
+    let total = area * d.iter().sum::<f64>() / 3.0;
+    let above = d.iter().filter(|x| **x > 0.0).count();
+    let pos = match above {
+        0 => 0.0,
+        3 => total,
+        1 => {
+            let [p, q, r] = rotate_to(d, |x| x > 0.0);
+            one(p, q, r)
+        }
+        _ => {
+            let [p, q, r] = rotate_to(d.map(|x| -x), |x| x >= 0.0);
+            total + one(p, q, r)
+        }
+    };
+
+    (pos, pos - total)
+}
+
+/// Rotates the array so the first element satisfying `f` is first.
+fn rotate_to<F: Fn(f64) -> bool>(mut d: [f64; 3], f: F) -> [f64; 3] {
+    let i = d.iter().position(|x| f(*x)).unwrap_or_default();
+    d.rotate_left(i);
+    d
+}
+
+/// Orders a triangle anti-clockwise.
+fn ccw(mut t: [Point2; 3]) -> [Point2; 3] {
+    if cross(t[0], t[1], t[2]) < 0.0 {
+        t.swap(1, 2);
+    }
+    t
+}
+
+/// A uniform grid over triangles' XY extents for overlap queries.
+pub(crate) struct TriGrid {
+    tris: Vec<Tri>,
+    origin: Point2,
+    cell: f64,
+    dims: [usize; 2],
+    cells: Vec<Vec<usize>>,
+}
+
+impl TriGrid {
+    pub fn new(tris: Vec<Tri>) -> Self {
+        let e = Extents2::from_iter(tris.iter().flat_map(|t| t.map(|[x, y, _]| [x, y])));
+
+        // aim for a few triangles per cell
+        let n = (tris.len().max(1) as f64).sqrt().ceil();
+        let cell = (e.size[0].max(e.size[1]) / n).max(1e-6);
+        let dims = e.size.map(|x| (x / cell).floor() as usize + 1);
+
+        let mut cells = vec![Vec::new(); dims[0] * dims[1]];
+        let mut this = Self {
+            tris: Vec::new(),
+            origin: e.origin,
+            cell,
+            dims,
+            cells: Vec::new(),
+        };
+        for (i, t) in tris.iter().enumerate() {
+            let [x0, y0, x1, y1] = this.range(Extents2::from(t.aabb()));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    cells[y * dims[0] + x].push(i);
+                }
+            }
+        }
+
+        this.tris = tris;
+        this.cells = cells;
+        this
+    }
+
+    /// The cell index range covering the extents (clamped).
+    fn range(&self, e: Extents2) -> [usize; 4] {
+        let f = |p: Point2| {
+            let [x, y] = p.sub(self.origin).scale(self.cell.recip());
+            [
+                x.max(0.0).min((self.dims[0] - 1) as f64) as usize,
+                y.max(0.0).min((self.dims[1] - 1) as f64) as usize,
+            ]
+        };
+        let ([x0, y0], [x1, y1]) = (f(e.origin), f(e.max()));
+        [x0, y0, x1, y1]
+    }
+
+    /// The triangles whose XY extents might overlap `e` (each triangle once).
+    pub fn query(&self, e: Extents2) -> impl Iterator<Item = &Tri> {
+        let [x0, y0, x1, y1] = self.range(e);
+        let mut idxs = (y0..=y1)
+            .flat_map(|y| (x0..=x1).map(move |x| (x, y)))
+            .flat_map(|(x, y)| self.cells[y * self.dims[0] + x].iter().copied())
+            .collect::<Vec<_>>();
+        idxs.sort_unstable();
+        idxs.dedup();
+        idxs.into_iter()
+            .map(|i| &self.tris[i])
+            .filter(move |t| Extents2::from(t.aabb()).intersects(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prism_splitting() {
+        let (p, n) = split_prism(1.0, [3.0, 3.0, 3.0]);
+        assert_eq!((p, n), (3.0, 0.0));
+        let (p, n) = split_prism(1.0, [-3.0, -3.0, -3.0]);
+        assert_eq!((p, n), (0.0, 3.0));
+
+        // symmetric about zero splits evenly
+        let (p, n) = split_prism(6.0, [1.0, -1.0, 0.0]);
+        assert!((p - n).abs() < 1e-12);
+        let (p, n) = split_prism(6.0, [1.0, -2.0, 1.0]);
+        assert!((p - n - 0.0).abs() < 1e-12, "{p} {n}");
+        let (p, n) = split_prism(6.0, [2.0, -1.0, -1.0]);
+        assert!((p - n).abs() < 1e-12, "{p} {n}");
+    }
+
+    fn world() -> Extents3 {
+        Extents3 {
+            origin: [0.0, 0.0, 100.0].into(),
+            size: [4064.0, 4064.0, 100.0].into(),
+        }
+    }
+
+    #[test]
+    fn tile_volumes() {
+        // A is 1 m above B over the whole root tile
+        let a = vec![2.0 / 4064.0; COUNT.pow(2)];
+        let b = vec![1.0 / 4064.0; COUNT.pow(2)];
+
+        let v = VolumeReport::from_tiles(&world(), 0, &a, &b, &[]).unwrap();
+        let area = 4064.0f64.powi(2);
+        assert!((v.fill() - area).abs() / area < 1e-5, "{}", v.fill());
+        assert_eq!(v.cut(), 0.0);
+
+        let v = VolumeReport::from_tiles(&world(), 0, &b, &a, &[]).unwrap();
+        assert!((v.net() + area).abs() / area < 1e-5);
+        assert_eq!(v.tiles(), vec![0]);
+
+        // clip to a 100 m square
+        let sq = [10.0, 10.0, 110.0, 10.0, 110.0, 110.0, 10.0, 110.0];
+        let v = VolumeReport::from_tiles(&world(), 0, &a, &b, &sq).unwrap();
+        assert!((v.fill() - 10_000.0).abs() < 0.1, "{}", v.fill());
+
+        assert!(VolumeReport::from_tiles(&world(), 0, &a, &b, &[0.0]).is_err());
+    }
+
+    fn square(z: f32, size: f32) -> TriangleMeshSurface {
+        TriangleMeshSurface {
+            translate: Point3::zero(),
+            points: vec![0., 0., z, size, 0., z, size, size, z, 0., size, z],
+            indices: vec![0, 1, 2, 2, 3, 0],
+        }
+    }
+
+    #[test]
+    fn mesh_volumes() {
+        // A is a tilted plane crossing B at x = 2000
+        let a = TriangleMeshSurface {
+            translate: Point3::zero(),
+            points: vec![
+                0., 0., 110., 4000., 0., 90., 4000., 4000., 90., 0., 4000., 110.,
+            ],
+            indices: vec![0, 1, 2, 2, 3, 0],
+        };
+        let b = square(100.0, 4000.0);
+
+        let v = VolumeReport::between_meshes(&a, &b, &world(), 1, &[]).unwrap();
+        // two wedges of 0.5 * 2000 * 10 * 4000
+        assert!((v.fill() - 40_000_000.0).abs() < 1.0, "{}", v.fill());
+        assert!((v.cut() - 40_000_000.0).abs() < 1.0, "{}", v.cut());
+        assert_eq!(v.tiles().len(), 4);
+
+        let mut m = VolumeReport::default();
+        m.merge(&v);
+        m.merge(&v);
+        assert!((m.fill() - 80_000_000.0).abs() < 1.0);
+
+        let sq = [0.0, 0.0, 1000.0, 0.0, 1000.0, 1000.0, 0.0, 1000.0];
+        let v = VolumeReport::between_meshes(&square(101.0, 4000.0), &b, &world(), 0, &sq).unwrap();
+        assert!((v.fill() - 1_000_000.0).abs() < 1e-3);
+    }
+}
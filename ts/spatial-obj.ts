@@ -3,6 +3,8 @@ export type SpatialObject = {
     status: Status,
     roots: Array<number>,
     tiles: Array<number>,
+    /** The world elevation of zero thickness, for derived difference (isopach) objects. */
+    datum?: number,
 }
 
 export enum Status {
@@ -1,7 +1,7 @@
 import { SpatialObject, Status, add_root, has_root, has_tile, add_tile } from "./spatial-obj";
 import { IDBPDatabase } from "idb";
 import * as idb from "idb";
-import { TriangleMeshSurface, Extents3, TileId } from './wasm';
+import { TriangleMeshSurface, Extents3, TileId, TileIndex } from './wasm';
 
 enum Key {
 	DataExtents = 'data-extents',
@@ -114,6 +114,7 @@ export class Store {
 
 			for (const t of sobj.tiles)
 				await store.delete(obj_tile_key(obj, t));
+			await store.delete(obj_index_key(obj));
 		});
 
 		return Promise.all([update_sobjs, rm_raw, rm_tiles]);
@@ -162,6 +163,20 @@ export class Store {
 		});
 	}
 
+	/** Store the object's tile index, see `TileIndex`. */
+	async store_tile_index(obj: string, index: TileIndex) {
+		await this.transact('tiles', 'readwrite', store =>
+			store.put_bytes(obj_index_key(obj), index.to_bytes()));
+	}
+
+	async get_tile_index(obj: string): Promise<TileIndex | undefined> {
+		return this.transact('tiles', 'readonly', async store => {
+			const bytes = await store.get_bytes(obj_index_key(obj));
+			if (bytes) return TileIndex.from_bytes(bytes);
+			else return undefined;
+		});
+	}
+
 	async get_tile(obj: string, tile_idx: number): Promise<Float32Array | undefined> {
 		return this.transact('tiles', 'readonly', async store => {
 			const bytes = await store.get_bytes(obj_tile_key(obj, tile_idx));
@@ -206,3 +221,7 @@ class TypedStore<M extends IDBTransactionMode> {
 function obj_tile_key(obj: string, tile: number): string {
 	return `${obj}/${tile}`;
 }
+
+function obj_index_key(obj: string): string {
+	return `${obj}/index`;
+}
@@ -1,6 +1,6 @@
 import * as BABYLON from "@babylonjs/core";
 import { Vector3, Scene } from "@babylonjs/core";
-import { Extents3, Viewbox } from "../wasm";
+import { Extents3, Viewbox, Camera as LodCamera } from "../wasm";
 import { WorldAxes } from "./world-axes";
 
 // the camera draw z level
@@ -163,16 +163,18 @@ export class Camera {
         // 1. the canvas aspect ratio impacts which axis is the bounded one
         // 2. the camera needs to maintain 1:1 ratio for x:z
 
-        const scaler = extents.max_dim();
-        let dx = 0.5 * extents.size.x / scaler;
-        let dy = 0.5 * extents.size.y / scaler;
+        // render space goes from (0,0) -> (1,1)
+        // but since we keep aspect ratio, using (.5, .5) will only center max dim
+        const { origin, size } = extents;
+        const c = extents.world_to_render(
+            origin.x + 0.5 * size.x, origin.y + 0.5 * size.y, origin.z);
+        const center = new Vector3(c.x, c.y, c.z);
+        let dx = c.x;
+        let dy = c.z;
 
         const rect = canvas.getBoundingClientRect();
         const canvasAspect = rect.width / rect.height;
         const extentsAspect = dx / dy;
-        // render space goes from (0,0) -> (1,1)
-        // but since we keep aspect ratio, using (.5, .5) will only center max dim
-        const center = new Vector3(dx, 0, dy);
 
         if (extentsAspect > canvasAspect) {
             // there is more x than canvas aspect ratio, bound x
@@ -282,25 +284,48 @@ export class Camera {
 
     /* Calculate the _render_ space extents of the view.
         *
-        * This uses 4 screen projections from the corners of the canvas.
+        * This uses the ortho bounds for orthographic views (eg plan and section views),
+        * or the view frustum for perspective views.
+        * Returns `undefined` if the view is degenerate (eg a zero sized canvas).
         */
-    viewbox(canvas: HTMLCanvasElement, extents: Extents3): Viewbox {
-        const { width, height } = canvas.getBoundingClientRect();
-        const view_dir = this.inner.target.subtract(this.inner.position);
+    viewbox(_canvas: HTMLCanvasElement, extents: Extents3): Viewbox | undefined {
+        try {
+            return this.viewbox_(extents);
+        } catch (e) {
+            console.warn(`unable to calculate viewbox: ${e}`);
+            return undefined;
+        }
+    }
 
-        const raybl = this.scene.createPickingRay(0, height, null, this.inner);
-        const raybr = this.scene.createPickingRay(width, height, null, this.inner);
-        const raytr = this.scene.createPickingRay(width, 0, null, this.inner);
-        const raytl = this.scene.createPickingRay(0, 0, null, this.inner);
+    private viewbox_(extents: Extents3): Viewbox {
+        if (this.inner.mode == BABYLON.Camera.PERSPECTIVE_CAMERA) {
+            // cull against the view frustum
+            const m = this.inner.getTransformationMatrix().m;
+            return Viewbox.from_view_projection(extents, new Float64Array(m));
+        }
+
+        // the ortho bounds are relative to the camera, along its right and up axes
+        const view_dir = this.inner.target.subtract(this.inner.position);
+        const up = this.inner.getDirection(Vector3.Up());
+        const camera = this.inner.position.asArray().concat(view_dir.asArray(), up.asArray());
+        const { otop, obot, olef, orig } = this.get_ortho();
 
-        const viewbox_data = view_dir.asArray().concat(
-            raybl.origin.asArray(),
-            raybr.origin.asArray(),
-            raytr.origin.asArray(),
-            raytl.origin.asArray(),
+        return Viewbox.orthographic(
+            extents,
+            new Float64Array(camera),
+            new Float64Array([olef, orig, obot, otop])
         );
+    }
+
+    /* The camera for choosing per-tile LODs, only used for perspective views.
+        */
+    lod_camera(canvas: HTMLCanvasElement): LodCamera | undefined {
+        if (this.inner.mode != BABYLON.Camera.PERSPECTIVE_CAMERA)
+            return undefined;
 
-        return Viewbox.calculate(extents, new Float64Array(viewbox_data));
+        const { height } = canvas.getBoundingClientRect();
+        const p = this.inner.position;
+        return LodCamera.new(p.x, p.y, p.z, this.inner.fov, height);
     }
 }
 
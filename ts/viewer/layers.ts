@@ -1,5 +1,5 @@
 import { Color3, Mesh, Scene, StandardMaterial, VertexData } from "@babylonjs/core";
-import { Extents3 } from "./../wasm";
+import { Extents3, TileIndex } from "./../wasm";
 import { Properties } from './prop';
 import { Store } from '../store';
 import { WorkerApi, spawn_pool } from "../worker-spawn";
@@ -12,7 +12,11 @@ export class Layers {
 	_store: Store;
 	_scene: Scene;
 	_loaded: Map<ObjKey, SpatialObject> = new Map();
+	_indices: Map<ObjKey, TileIndex> = new Map();
+	/* object/tile -> vertex data built ahead of the tile coming into view */
+	_prefetched: Map<string, Promise<VertexData>> = new Map();
 	_tiles: Map<number, Tile[]> = new Map();
+	_wanted: Set<number> = new Set();
 	_def_props: Properties = new Properties();
 	_wkr?: EsThreadPool<WorkerApi>;
 	_update_ver: number = 0;
@@ -27,8 +31,26 @@ export class Layers {
 		return this._loaded.has(obj);
 	}
 
+	object(obj: ObjKey): SpatialObject | undefined {
+		return this._loaded.get(obj);
+	}
+
+	/* The object's tile index, if it has been preprocessed with one. */
+	object_index(obj: ObjKey): TileIndex | undefined {
+		return this._indices.get(obj);
+	}
+
+	loaded(): IterableIterator<[ObjKey, SpatialObject]> {
+		return this._loaded.entries();
+	}
+
 	unload(obj: ObjKey) {
 		this._loaded.delete(obj);
+		this._indices.delete(obj);
+		for (const key of this._prefetched.keys()) {
+			if (key.startsWith(`${obj}/`))
+				this._prefetched.delete(key);
+		}
 
 		for (const ts of this._tiles.values()) {
 			for (const t of ts) {
@@ -45,14 +67,17 @@ export class Layers {
 	 */
 	async add_surface(obj: ObjKey) {
 		const st = this._store;
-		const [sobj, extents] = await Promise.all([
+		const [sobj, extents, index] = await Promise.all([
 			st.find_object(obj),
-			st.extents()
+			st.extents(),
+			st.get_tile_index(obj)
 		]);
 		if (!sobj || !extents)
 			return;
 
 		this._loaded.set(obj, sobj);
+		if (index)
+			this._indices.set(obj, index);
 
 		return Promise.all(sobj.roots.map(async tile => {
 			const zs = await this._store.get_tile(obj, tile);
@@ -74,7 +99,9 @@ export class Layers {
 
 		apply_prop_to_mesh(mesh, this._def_props, this._scene);
 
-		const vd = await this.build_tile_vertex_data(extents, tile, zs);
+		const pre = this._prefetched.get(mesh.name);
+		this._prefetched.delete(mesh.name);
+		const vd = await (pre ?? this.build_tile_vertex_data(extents, tile, zs));
 		vd.applyToMesh(mesh);
 		tiles.push(new Tile(obj, mesh));
 	}
@@ -105,6 +132,7 @@ export class Layers {
 		const outview = new Set(outview_tiles);
 		const to_remove = Array.from(this._tiles.keys())
 			.filter(x => !inview.has(x) && !outview.has(x));
+		this._wanted = new Set([...inview, ...outview]);
 
 		// first, add all the _new_ in view tiles
 		// note that this will update over already existing meshes
@@ -115,13 +143,7 @@ export class Layers {
 			if (ver != this._update_ver)
 				break; // version changed
 
-			const tiles = this._tiles.get(tile_idx);
-			if (tiles) {
-				for (const tile of tiles)
-					tile.dispose();
-				this._tiles.delete(tile_idx);
-			}
-
+			this.dispose_tile(tile_idx);
 			onload();
 		}
 
@@ -129,6 +151,90 @@ export class Layers {
 		await this.add_tiles(outview, extents, ver, onload);
 	}
 
+	/* Apply the tile change sets from `ViewableTiles`.
+	 *
+	 * The added tiles are loaded (in order) before the removed tiles are disposed of.
+	 * Since change sets build on each other, these are never skipped; instead a tile which is
+	 * no longer wanted by the time it loads is disposed of.
+	 */
+	async apply_tile_changes(added: number[], removed: number[], extents: Extents3, onload: () => void) {
+		for (const tile_idx of removed)
+			this._wanted.delete(tile_idx);
+		for (const tile_idx of added)
+			this._wanted.add(tile_idx);
+
+		await Promise.all(added.map(async tile_idx => {
+			if (this._tiles.has(tile_idx))
+				return; // no change already loaded
+
+			for (const obj of this._loaded.values()) {
+				if (!has_tile(obj, tile_idx))
+					continue;
+
+				const zs = await this._store.get_tile(obj.key, tile_idx);
+				if (zs && this._wanted.has(tile_idx))
+					await this.add_surface_tile(extents, obj.key, tile_idx, zs);
+			}
+
+			if (!this._wanted.has(tile_idx))
+				this.dispose_tile(tile_idx);
+			onload();
+		}));
+
+		for (const tile_idx of removed) {
+			if (!this._wanted.has(tile_idx))
+				this.dispose_tile(tile_idx);
+		}
+		onload();
+	}
+
+	/* Build the vertex data of the tiles expected to come into view, ahead of time.
+	 *
+	 * Only the latest prefetch tiles are kept, and their vertex data is used once they are added.
+	 * Call this after the in view tiles are queued, so the prefetching is at a lower priority.
+	 */
+	async prefetch_tiles(tiles: number[], extents: Extents3) {
+		const keep = new Set<string>();
+		for (const tile_idx of tiles) {
+			if (this._tiles.has(tile_idx))
+				continue; // already loaded
+
+			for (const obj of this._loaded.values()) {
+				if (!has_tile(obj, tile_idx))
+					continue;
+
+				const key = `${obj.key}/${tile_idx}`;
+				keep.add(key);
+				if (!this._prefetched.has(key)) {
+					const vd = this._store.get_tile(obj.key, tile_idx).then(zs =>
+						zs ? this.build_tile_vertex_data(extents, tile_idx, zs) : new VertexData());
+					this._prefetched.set(key, vd);
+					// a failed prefetch is dropped, so the tile is built again once added
+					vd.catch(() => {
+						if (this._prefetched.get(key) === vd)
+							this._prefetched.delete(key);
+					});
+				}
+			}
+		}
+
+		for (const key of this._prefetched.keys()) {
+			if (!keep.has(key))
+				this._prefetched.delete(key);
+		}
+
+		await Promise.all(this._prefetched.values());
+	}
+
+	private dispose_tile(tile_idx: number) {
+		const tiles = this._tiles.get(tile_idx);
+		if (tiles) {
+			for (const tile of tiles)
+				tile.dispose();
+			this._tiles.delete(tile_idx);
+		}
+	}
+
 	private async add_tiles(tiles: Iterable<number>, extents: Extents3, ver: number, onload: () => void) {
 		const db = this._store;
 		await Promise.all(Array.from(tiles).map(async tile_idx => {
@@ -23,6 +23,7 @@ export class Viewer {
     _rpipeline: BABYLON.DefaultRenderingPipeline;
 
     private static TILE_LOD_TIMEOUT: number = 200; // wait before loading
+    private static TILE_BUDGET: number = 512; // max in view tiles
 
     private constructor(canvas: HTMLCanvasElement, store: Store) {
         this.canvas = canvas;
@@ -99,7 +100,11 @@ export class Viewer {
             const xs = this.extents;
             const viewbox = this.camera.viewbox(this.canvas, xs);
             this.tiler = ViewableTiles.new(xs);
-            this.tiler.update(viewbox);
+            this.tiler.set_tile_budget(Viewer.TILE_BUDGET);
+            for (const [key, _] of this.layers.loaded())
+                this.set_tiler_object(key);
+            if (viewbox)
+                this.tiler.update(viewbox);
         }
 
         return this.tiler;
@@ -145,15 +150,28 @@ export class Viewer {
         console.time(`loading object ${key}`);
         await this.layers.add_surface(key);
         console.timeEnd(`loading object ${key}`);
-        await this.update_in_view_tiles();
+        this.set_tiler_object(key);
+        await this.update_in_view_tiles(true);
         this.mark_dirty();
     }
 
+    /* Tell the tiler which tiles hold the object's data, and their height ranges if indexed.
+        */
+    private set_tiler_object(key: string) {
+        const index = this.layers.object_index(key);
+        const sobj = this.layers.object(key);
+        if (index)
+            this.tiler?.set_object_index(key, index);
+        else if (sobj)
+            this.tiler?.set_object_tiles(key, new Uint32Array(sobj.tiles));
+    }
+
     async unload_object(key: string) {
         if (!this.layers.is_loaded(key))
             return;
 
         this.layers.unload(key);
+        this.tiler?.remove_object(key);
         this.mark_dirty();
     }
 
@@ -216,7 +234,10 @@ export class Viewer {
         setTimeout(cb, 1000); // check again after a second
     }
 
-    private async update_in_view_tiles() {
+    /* Update the tiles in view, applying only the changes since the last update unless `full`
+        * (eg when an object is loaded).
+        */
+    private async update_in_view_tiles(full: boolean = false) {
         if (!this.tiler) {
             // tiler not initialised, fire off to initialise it and return, not waiting
             // a subsequent update will process it
@@ -230,15 +251,31 @@ export class Viewer {
         // const updateKey = `recalculate viewbox ${Math.random()}`;
         // console.time(updateKey);
         const viewbox = this.camera.viewbox(this.canvas, this.extents);
-        this.tiler.update(viewbox);
+        if (!viewbox)
+            return; // nothing sensible in view, keep the current tiles
+        const lod_camera = this.camera.lod_camera(this.canvas);
+        if (lod_camera)
+            this.tiler.update_with_camera(viewbox, lod_camera);
+        else
+            this.tiler.update(viewbox);
         // console.timeEnd(updateKey)
 
-        const inview = this.tiler.in_view_tiles();
-        const outview = this.tiler.out_view_tiles();
-
-        // console.debug({inview, outview});
+        const onload = () => this.mark_dirty();
+        if (full) {
+            const inview = this.tiler.in_view_tiles();
+            const outview = this.tiler.out_view_tiles();
+            // console.debug({inview, outview});
+            await this.layers.update_inview_tiles(inview, outview, this.extents, onload);
+        } else {
+            const added = Array.from(this.tiler.added());
+            const removed = Array.from(this.tiler.removed());
+            await this.layers.apply_tile_changes(added, removed, this.extents, onload);
+        }
 
-        await this.layers.update_inview_tiles(inview, outview, this.extents, () => this.mark_dirty());
+        // warm the cache with the tiles coming into view, not waiting since it is not viewed yet
+        const prefetch = Array.from(this.tiler.prefetch_tiles());
+        this.layers.prefetch_tiles(prefetch, this.extents)
+            .catch(e => console.warn(`failed to prefetch tiles: ${e}`));
 
         // do last since it does not affect viewing
         // don't mark dirty, does not need a render
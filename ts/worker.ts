@@ -4,11 +4,12 @@ import * as local_loader from './local-loader';
 import { Store } from './store';
 import * as wasm from './wasm';
 import * as prgrs from './prg-stream';
-import { FlatTreeItem } from './spatial-obj';
+import { FlatTreeItem, Status, has_tile } from './spatial-obj';
 
 const api = {
 	read_load_and_store_from_spatial_file,
 	preprocess_spatial_object,
+	derive_difference_object,
 	build_vertex_data
 };
 
@@ -54,23 +55,95 @@ async function preprocess_spatial_object(
 
 	const outof = tiles.length;
 	const pr = progress.send.getWriter();
+	const index = wasm.TileIndex.new();
 	let iter = 0;
 
 	for (const tile_idx of tiles) {
-		const zs = hash.sample(tile_idx);
-		if (zs) {
+		const sample = hash.sample(tile_idx);
+		if (sample) {
+			const zs = sample.zs();
+			index.insert(tile_idx, sample);
+			sample.free(); // the zs are copied out, free the wasm copy now
 			await db.store_tile(objkey, tile_idx, zs);
 			iter += 1;
 			await pr.write(prgrs.preprocessing(objkey, iter, outof));
 		}
 	}
 
+	await db.store_tile_index(objkey, index);
+
 	await pr.ready.then(() => pr.releaseLock());
 	await progress.send.close();
 
 	return chgd;
 }
 
+/** Stores the difference (isopach) surface `a` less `b` as the object `objkey`.
+ * The tiles are derived from the overlapping tiles of the two objects, so both need to be
+ * preprocessed first. Returns if any tiles were stored. */
+async function derive_difference_object(
+	db_name: string,
+	a: string,
+	b: string,
+	objkey: string,
+	progress: prgrs.Channel
+): Promise<boolean> {
+	const db = await Store.connect(db_name);
+	const extents = await db.extents();
+	const [sa, sb] = await Promise.all([db.find_object(a), db.find_object(b)]);
+	if (!extents || !sa || !sb)
+		return false;
+
+	// the derived object has no raw data, only tiles
+	await db.delete_object(objkey);
+	await db.update_object_list({ key: objkey, status: Status.Preprocessing, roots: [], tiles: [] });
+
+	const tiles = sa.tiles.filter(t => has_tile(sb, t));
+	const outof = tiles.length;
+	const pr = progress.send.getWriter();
+	const load = (tile_idx: number) => Promise.all([db.get_tile(a, tile_idx), db.get_tile(b, tile_idx)]);
+
+	// the thickness is stored about a datum which keeps it within the data extents, so the
+	// thickness range is found first
+	const isopach = wasm.Isopach.new(extents);
+	for (const tile_idx of tiles) {
+		const [za, zb] = await load(tile_idx);
+		if (za && zb)
+			isopach.include(za, zb);
+	}
+	if (!isopach.fits())
+		console.warn(`the thickness of ${objkey} exceeds the data's z extents, so it may be culled from view`);
+
+	const index = wasm.TileIndex.new();
+	let iter = 0;
+
+	for (const tile_idx of tiles) {
+		const [za, zb] = await load(tile_idx);
+		const zs = za && zb && isopach.tile_zs(za, zb);
+		if (zs) {
+			await db.store_tile(objkey, tile_idx, zs);
+			index.insert_zs(tile_idx, zs);
+			iter += 1;
+			await pr.write(prgrs.preprocessing(objkey, iter, outof));
+		}
+	}
+
+	await db.store_tile_index(objkey, index);
+	await pr.ready.then(() => pr.releaseLock());
+	await progress.send.close();
+
+	const sobj = await db.find_object(objkey);
+	if (sobj) {
+		sobj.status = Status.Ready;
+		// recorded for colour ramps
+		sobj.datum = isopach.datum();
+		await db.update_object_list(sobj);
+	}
+
+	isopach.free();
+	return iter > 0;
+}
+
 export type MeshVertexData = {
 	empty: boolean,
 	positions: ArrayBuffer,